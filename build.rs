@@ -0,0 +1,35 @@
+// SPDX-License-Identifier: GPL-3.0-only
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=include/cosmic_plugin.h");
+
+    // the checked-in header is regenerated explicitly with
+    // `cbindgen --output include/cosmic_plugin.h`, the build only makes sure
+    // it matches the ABI of the crate
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+        .expect("invalid cbindgen.toml");
+    let bindings = cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(crate_dir.join("src/lib.rs"))
+        .generate()
+        .expect("failed to generate the C header");
+    let mut generated = Vec::new();
+    bindings.write(&mut generated);
+    let header = crate_dir.join("include/cosmic_plugin.h");
+    if std::fs::read(&header).ok().as_ref() != Some(&generated) {
+        let path = out_dir.join("cosmic_plugin.h");
+        std::fs::write(&path, &generated).expect("failed to write the C header");
+        panic!(
+            "{} is out of date, regenerate it with `cbindgen --output include/cosmic_plugin.h` \
+             or compare it with {}",
+            header.display(),
+            path.display()
+        );
+    }
+}
@@ -0,0 +1,187 @@
+/* SPDX-License-Identifier: GPL-3.0-only */
+/*
+ * A minimal plugin written in C.
+ *
+ * Build it with:
+ *   cc -shared -fPIC -I../../include $(pkg-config --cflags gtk4) \
+ *       hello.c $(pkg-config --libs gtk4) -o libhello_c.so
+ *
+ * and load it with:
+ *   LD_LIBRARY_PATH=. cargo run --example load_plugin -- hello_c
+ */
+#include <stdlib.h>
+
+#include "cosmic_plugin.h"
+
+typedef struct {
+  /* must be the first field */
+  Plugin header;
+  GtkWidget *label;
+} HelloPlugin;
+
+static PluginStatus hello_applet(void *self, GtkBox **applet) {
+  HelloPlugin *plugin = self;
+  GtkWidget *box = gtk_box_new(GTK_ORIENTATION_HORIZONTAL, 0);
+  plugin->label = gtk_label_new("Hello from C");
+  gtk_box_append(GTK_BOX(box), plugin->label);
+  /* the host takes ownership of the returned reference */
+  *applet = GTK_BOX(g_object_ref_sink(box));
+  return PluginStatus_Ok;
+}
+
+static PluginStatus hello_set_size(void *self, Size size) {
+  (void)self;
+  (void)size;
+  return PluginStatus_Ok;
+}
+
+static PluginStatus hello_set_position(void *self, Position position) {
+  HelloPlugin *plugin = self;
+  if (plugin->label == NULL)
+    return PluginStatus_Ok;
+  switch (position) {
+  case Position_Start:
+  case Position_End:
+    gtk_label_set_text(GTK_LABEL(plugin->label), "Hi");
+    break;
+  case Position_Top:
+  case Position_Bottom:
+    gtk_label_set_text(GTK_LABEL(plugin->label), "Hello from C");
+    break;
+  }
+  return PluginStatus_Ok;
+}
+
+static PluginStatus hello_css_provider(void *self, GtkCssProvider **css_provider) {
+  (void)self;
+  *css_provider = gtk_css_provider_new();
+  return PluginStatus_Ok;
+}
+
+static PluginStatus hello_on_plugin_load(void *self, const ByteSink *error) {
+  static const char message[] = "GTK is not initialized";
+  (void)self;
+  if (!gtk_is_initialized()) {
+    error->write(error->ctx, (const uint8_t *)message, sizeof(message) - 1);
+    return PluginStatus_Failed;
+  }
+  return PluginStatus_Ok;
+}
+
+static PluginStatus hello_on_plugin_unload(void *self) {
+  (void)self;
+  return PluginStatus_Ok;
+}
+
+/* the plugin has no state, so the sink is never written to */
+static PluginStatus hello_save_state(void *self, const ByteSink *out) {
+  (void)self;
+  (void)out;
+  return PluginStatus_Ok;
+}
+
+static PluginStatus hello_restore_state(void *self, const ByteSlice *state) {
+  (void)self;
+  (void)state;
+  return PluginStatus_Ok;
+}
+
+static PluginStatus hello_state_version(void *self, uint32_t *version) {
+  (void)self;
+  *version = 0;
+  return PluginStatus_Ok;
+}
+
+static PluginStatus hello_on_suspend(void *self) {
+  (void)self;
+  return PluginStatus_Ok;
+}
+
+static PluginStatus hello_on_resume(void *self) {
+  (void)self;
+  return PluginStatus_Ok;
+}
+
+/* settings are passed as RON or TOML text, which is empty if none are
+ * stored; this plugin has no settings */
+static PluginStatus hello_on_settings_changed(void *self,
+                                              const RawSettings *settings) {
+  (void)self;
+  (void)settings;
+  return PluginStatus_Ok;
+}
+
+/* the kind of the event is one of the HOST_EVENT_ constants, its data the
+ * event serialized with RON; this plugin does not depend on its environment */
+static PluginStatus hello_on_host_event(void *self, const RawHostEvent *event) {
+  (void)self;
+  (void)event;
+  return PluginStatus_Ok;
+}
+
+/* without a schema the sink is not written to and no preferences page is
+ * generated */
+static PluginStatus hello_settings_schema(void *self, const ByteSink *out) {
+  (void)self;
+  (void)out;
+  return PluginStatus_Ok;
+}
+
+/* stands in for the Rust ABI entries, which the host never calls */
+static void rust_only(void) { abort(); }
+
+static void hello_drop(void *self) { free(self); }
+
+static const PluginVtable hello_vtable = {
+    ._applet = hello_applet,
+    ._set_size = hello_set_size,
+    ._set_position = hello_set_position,
+    ._css_provider = hello_css_provider,
+    ._on_plugin_load = hello_on_plugin_load,
+    ._on_plugin_unload = hello_on_plugin_unload,
+    ._save_state = hello_save_state,
+    ._restore_state = hello_restore_state,
+    ._state_version = hello_state_version,
+    ._on_suspend = hello_on_suspend,
+    ._on_resume = hello_on_resume,
+    ._on_settings_changed = hello_on_settings_changed,
+    ._on_host_event = hello_on_host_event,
+    ._settings_schema = hello_settings_schema,
+    .applet = rust_only,
+    .css_provider = rust_only,
+    .set_size = rust_only,
+    .set_position = rust_only,
+    .on_plugin_load = rust_only,
+    .on_plugin_unload = rust_only,
+    .save_state = rust_only,
+    .restore_state = rust_only,
+    .state_version = rust_only,
+    .on_suspend = rust_only,
+    .on_resume = rust_only,
+    .on_settings_changed = rust_only,
+    .on_host_event = rust_only,
+    .settings_schema = rust_only,
+    .drop = hello_drop,
+};
+
+const PluginAbi _plugin_abi = {
+    .version = PLUGIN_ABI_VERSION,
+    .vtable_size = sizeof(PluginVtable),
+    .vtable_align = _Alignof(PluginVtable),
+};
+
+const PluginDescriptor _plugin_descriptor = {
+    .name = "Hello C",
+    .version = "0.1.0",
+    .authors = "",
+    .description = "An example plugin written in C",
+    .icon = "",
+};
+
+Plugin *_plugin_create(void) {
+  HelloPlugin *plugin = calloc(1, sizeof(HelloPlugin));
+  if (plugin == NULL)
+    return NULL;
+  plugin->header.vtable = &hello_vtable;
+  return &plugin->header;
+}
@@ -0,0 +1,31 @@
+// SPDX-License-Identifier: GPL-3.0-only
+//! Load a plugin by library name and show its applet in a window, e.g.
+//! `cargo run --example load_plugin -- hello_c` for the plugin in `examples/c`.
+use cosmic_plugin::PluginManager;
+use gtk4::prelude::*;
+
+fn main() {
+    let name = std::env::args()
+        .nth(1)
+        .expect("usage: load_plugin <library name>");
+    gtk4::init().expect("failed to initialize gtk");
+
+    let mut manager = PluginManager::new();
+    let id = unsafe { manager.load_plugin(name) }.expect("failed to load the plugin");
+    let applet = manager.applet(id).unwrap();
+    gtk4::StyleContext::add_provider_for_display(
+        &gtk4::gdk::Display::default().expect("no display"),
+        &manager.css_provider(id).unwrap(),
+        gtk4::STYLE_PROVIDER_PRIORITY_APPLICATION,
+    );
+
+    let app = gtk4::Application::new(Some("com.system76.CosmicPluginExample"), Default::default());
+    app.connect_activate(move |app| {
+        let window = gtk4::ApplicationWindow::new(app);
+        window.set_child(Some(&applet));
+        window.present();
+    });
+    app.run_with_args::<&str>(&[]);
+
+    manager.unload_all();
+}
@@ -0,0 +1,191 @@
+/* SPDX-License-Identifier: GPL-3.0-only */
+
+#ifndef COSMIC_PLUGIN_H
+#define COSMIC_PLUGIN_H
+
+/* Generated by cbindgen from the cosmic-plugin sources, do not edit. */
+
+#include <stdbool.h>
+#include <stdint.h>
+#include <gtk/gtk.h>
+
+/**
+ * Version of the plugin ABI. Must be bumped whenever the `Plugin` trait, and
+ * with it the layout of [`PluginVtable`], or any other type crossing the
+ * library boundary changes.
+ */
+#define PLUGIN_ABI_VERSION 9
+
+#define HOST_EVENT_THEME 1
+
+#define HOST_EVENT_SCALE 2
+
+#define HOST_EVENT_PANEL_VISIBILITY 3
+
+#define HOST_EVENT_MONITOR 4
+
+#define HOST_EVENT_SESSION_LOCK 5
+
+/**
+ * Result of a call into a plugin. Panics must not unwind across the plugin
+ * boundary, so they are caught and reported to the host instead.
+ */
+typedef enum {
+  PluginStatus_Ok,
+  PluginStatus_Panicked,
+  /**
+   * A fallible hook returned an error, which was passed to its `ByteSink`.
+   */
+  PluginStatus_Failed,
+} PluginStatus;
+
+typedef enum {
+  Position_Start,
+  Position_End,
+  Position_Top,
+  Position_Bottom,
+} Position;
+
+/**
+ * Format of stored plugin settings.
+ */
+typedef enum {
+  SettingsFormat_Ron,
+  SettingsFormat_Toml,
+} SettingsFormat;
+
+typedef enum {
+  Size_Small,
+  Size_Medium,
+  Size_Large,
+} Size;
+
+/**
+ * ABI fingerprint exported by every plugin library as `_plugin_abi`.
+ * [`PluginManager`] checks it before calling the plugin constructor, so a
+ * plugin built against an incompatible version of this crate is rejected
+ * instead of being cast into a [`BoxedPlugin`] with the wrong vtable layout.
+ */
+typedef struct {
+  /**
+   * Must remain the first field so the version of any plugin can be read.
+   */
+  uint32_t version;
+  uint32_t vtable_size;
+  uint32_t vtable_align;
+} PluginAbi;
+
+/**
+ * Metadata exported by every plugin library as `_plugin_descriptor`, which
+ * lets the host identify a plugin without instantiating it. All strings are
+ * nul-terminated UTF-8 and may be null if unset.
+ */
+typedef struct {
+  const char *name;
+  const char *version;
+  const char *authors;
+  const char *description;
+  const char *icon;
+} PluginDescriptor;
+
+/**
+ * Callback passed to plugins which copies data out of the plugin, e.g. the
+ * snapshot passed to `_save_state`, the error message of `_on_plugin_load` or
+ * the RON serialized [`SettingsSchema`] passed to `_settings_schema`.
+ * It is not called if there is no data.
+ */
+typedef void (*ByteWriter)(void *ctx, const uint8_t *data, uintptr_t len);
+
+/**
+ * A [`ByteWriter`] along with the context it must be called with.
+ */
+typedef struct {
+  ByteWriter write;
+  void *ctx;
+} ByteSink;
+
+/**
+ * Data passed into a plugin, e.g. the snapshot passed to `_restore_state`.
+ * `data` may be null if `len` is 0.
+ */
+typedef struct {
+  const uint8_t *data;
+  uintptr_t len;
+} ByteSlice;
+
+/**
+ * [`Settings`] as passed to `_on_settings_changed`.
+ */
+typedef struct {
+  SettingsFormat format;
+  ByteSlice data;
+} RawSettings;
+
+/**
+ * A [`HostEvent`] as passed to `_on_host_event`: its kind, one of the
+ * `HOST_EVENT_*` constants, and the event serialized with ron.
+ */
+typedef struct {
+  uint32_t kind;
+  ByteSlice data;
+} RawHostEvent;
+
+/**
+ * The dispatch table of a plugin object, in declaration order of the `Plugin`
+ * trait methods followed by `drop`.
+ *
+ * The host only ever calls the `_`-prefixed entries. The remaining entries use
+ * the Rust ABI and are opaque here; plugins written in other languages must
+ * still point them at a function, e.g. one which aborts, as they may not be
+ * null.
+ */
+typedef struct {
+  PluginStatus (*_applet)(void*, GtkBox**);
+  PluginStatus (*_set_size)(void*, Size);
+  PluginStatus (*_set_position)(void*, Position);
+  PluginStatus (*_css_provider)(void*, GtkCssProvider**);
+  PluginStatus (*_on_plugin_load)(void*, const ByteSink*);
+  PluginStatus (*_on_plugin_unload)(void*);
+  PluginStatus (*_save_state)(void*, const ByteSink*);
+  PluginStatus (*_restore_state)(void*, const ByteSlice*);
+  PluginStatus (*_state_version)(void*, uint32_t*);
+  PluginStatus (*_on_suspend)(void*);
+  PluginStatus (*_on_resume)(void*);
+  PluginStatus (*_on_settings_changed)(void*, const RawSettings*);
+  PluginStatus (*_on_host_event)(void*, const RawHostEvent*);
+  PluginStatus (*_settings_schema)(void*, const ByteSink*);
+  void (*applet)(void);
+  void (*css_provider)(void);
+  void (*set_size)(void);
+  void (*set_position)(void);
+  void (*on_plugin_load)(void);
+  void (*on_plugin_unload)(void);
+  void (*save_state)(void);
+  void (*restore_state)(void);
+  void (*state_version)(void);
+  void (*on_suspend)(void);
+  void (*on_resume)(void);
+  void (*on_settings_changed)(void);
+  void (*on_host_event)(void);
+  void (*settings_schema)(void);
+  /**
+   * Free the plugin object.
+   */
+  void (*drop)(void*);
+} PluginVtable;
+
+/**
+ * A plugin object. The pointer returned by `_plugin_create` must point to an
+ * allocation starting with this header; the rest of it is owned by the plugin.
+ * `_plugin_create` returns null if the plugin could not be created.
+ */
+typedef struct {
+  const PluginVtable *vtable;
+} Plugin;
+
+/* Symbols exported by every plugin library. */
+extern const PluginAbi _plugin_abi;
+extern const PluginDescriptor _plugin_descriptor;
+Plugin *_plugin_create(void);
+
+#endif /* COSMIC_PLUGIN_H */
@@ -0,0 +1,180 @@
+// SPDX-License-Identifier: GPL-3.0-only
+//! Runs a single plugin for `PluginManager::load_plugin_isolated` and mirrors
+//! its applet to the dock over the socket inherited as
+//! [`HOST_SOCKET_FD`](cosmic_plugin::ipc::HOST_SOCKET_FD).
+//!
+//! Usage: `cosmic-plugin-host <library path> [<instance name>]`
+use cosmic_plugin::ipc::{
+    write_message, HostMessage, HostRequest, MessageReader, UiNode, HOST_SOCKET_FD,
+};
+use cosmic_plugin::{PluginEvent, PluginId, PluginManager, RestartPolicy, SettingsSchema};
+use gtk4::glib;
+use gtk4::prelude::*;
+use std::cell::RefCell;
+use std::os::unix::io::FromRawFd;
+use std::os::unix::net::UnixStream;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// How often the applet is checked for changes and the plugin for reloads.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+struct Host {
+    id: PluginId,
+    stream: UnixStream,
+    manager: PluginManager<'static>,
+    applet: gtk4::Box,
+    last_ui: Option<UiNode>,
+    last_schema: Option<Option<SettingsSchema>>,
+}
+
+impl Host {
+    fn send(&mut self, message: &HostMessage) {
+        if let Err(e) = write_message(&mut self.stream, message) {
+            eprintln!("failed to send message to the dock: {}", e);
+        }
+    }
+
+    fn send_ui(&mut self) {
+        let ui = UiNode::describe(self.applet.upcast_ref());
+        if self.last_ui.as_ref() != Some(&ui) {
+            self.send(&HostMessage::Ui(ui.clone()));
+            self.last_ui = Some(ui);
+        }
+    }
+
+    /// Send the settings schema whenever it changed, e.g. with the settings.
+    fn send_schema(&mut self) {
+        let schema = self.manager.settings_schema(self.id);
+        if self.last_schema.as_ref() != Some(&schema) {
+            self.send(&HostMessage::Schema(schema.clone()));
+            self.last_schema = Some(schema);
+        }
+    }
+
+    /// Exit with an error once the plugin is gone, the dock restarts the host.
+    fn fail(&mut self, message: String) -> ! {
+        self.send(&HostMessage::Error(message));
+        std::process::exit(1);
+    }
+}
+
+fn main() {
+    let mut args = std::env::args_os().skip(1);
+    let lib_path = match args.next() {
+        Some(p) => p,
+        None => {
+            eprintln!("usage: cosmic-plugin-host <library path> [<instance name>]");
+            std::process::exit(2);
+        }
+    };
+    let instance = args.next().map(|i| i.to_string_lossy().into_owned());
+    let mut stream = unsafe { UnixStream::from_raw_fd(HOST_SOCKET_FD) };
+    if let Err(e) = gtk4::init() {
+        let _ = write_message(&mut stream, &HostMessage::Error(e.to_string()));
+        std::process::exit(1);
+    }
+
+    // the dock restarts and watches the whole host instead
+    let mut manager = PluginManager::new();
+    manager.set_default_restart_policy(RestartPolicy::Never);
+    manager.set_watchdog_threshold(None);
+    let loaded = match &instance {
+        Some(instance) => unsafe { manager.load_plugin_instance_from_path(&lib_path, instance) },
+        None => unsafe { manager.load_plugin_from_path(&lib_path) },
+    };
+    let id = match loaded {
+        Ok(id) => id,
+        Err(e) => {
+            let _ = write_message(&mut stream, &HostMessage::Error(e.to_string()));
+            std::process::exit(1);
+        }
+    };
+    let applet = manager.applet(id).unwrap();
+    let css = manager.css_provider(id).unwrap().to_str().to_string();
+    let host = Rc::new(RefCell::new(Host {
+        id,
+        stream,
+        manager,
+        applet,
+        last_ui: None,
+        last_schema: None,
+    }));
+    {
+        let mut host = host.borrow_mut();
+        host.send(&HostMessage::Css(css));
+        host.send_schema();
+        host.send_ui();
+    }
+
+    let main_loop = glib::MainLoop::new(None, false);
+
+    let h = host.clone();
+    glib::timeout_add_local(REFRESH_INTERVAL, move || {
+        let mut host = h.borrow_mut();
+        for event in unsafe { host.manager.poll_events() } {
+            match event {
+                PluginEvent::Reloaded { applet, .. } => host.applet = applet,
+                PluginEvent::ReloadFailed { name, error, .. } => {
+                    eprintln!("failed to reload {}: {}", name, error)
+                }
+                PluginEvent::Faulted { name, .. } => host.fail(format!("{} panicked", name)),
+                PluginEvent::Restarted { applet, .. } => host.applet = applet,
+                PluginEvent::HealthChanged { .. } | PluginEvent::Moved { .. } => {}
+            }
+        }
+        host.send_schema();
+        host.send_ui();
+        glib::Continue(true)
+    });
+
+    let h = host.clone();
+    let l = main_loop.clone();
+    let mut reader = MessageReader::default();
+    glib::unix_fd_add_local(
+        HOST_SOCKET_FD,
+        glib::IOCondition::IN | glib::IOCondition::HUP | glib::IOCondition::ERR,
+        move |_, _| {
+            let mut host = h.borrow_mut();
+            let requests = match reader.read::<HostRequest>(&mut host.stream) {
+                Ok(Some(requests)) => requests,
+                // the dock is gone
+                Ok(None) | Err(_) => {
+                    l.quit();
+                    return glib::Continue(false);
+                }
+            };
+            for request in requests {
+                match request {
+                    HostRequest::SetSize(size) => host.manager.set_size(size),
+                    HostRequest::SetPosition(position) => host.manager.set_position(position),
+                    HostRequest::Activate(path) => {
+                        if let Some(button) = UiNode::find(host.applet.upcast_ref(), &path)
+                            .and_then(|w| w.downcast::<gtk4::Button>().ok())
+                        {
+                            button.emit_clicked();
+                        }
+                    }
+                    HostRequest::Ping => host.send(&HostMessage::Pong),
+                    HostRequest::Suspend | HostRequest::Resume => {
+                        let enabled = request == HostRequest::Resume;
+                        let id = host.id;
+                        if let Err(e) = host.manager.set_enabled(id, enabled) {
+                            eprintln!("{}", e);
+                        }
+                    }
+                    HostRequest::Event(ref event) => host.manager.send_event(event),
+                    HostRequest::Shutdown => {
+                        l.quit();
+                        return glib::Continue(false);
+                    }
+                }
+            }
+            host.send_ui();
+            glib::Continue(true)
+        },
+    );
+
+    main_loop.run();
+    host.borrow_mut().manager.unload_all();
+}
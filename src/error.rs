@@ -0,0 +1,59 @@
+// SPDX-License-Identifier: GPL-3.0-only
+use crate::{PluginAbi, PluginId, PluginState, Size};
+use gtk4::Orientation;
+use thiserror::Error;
+
+pub type Result<T, E = PluginError> = std::result::Result<T, E>;
+
+/// Errors returned by [`PluginManager`](crate::PluginManager).
+#[derive(Debug, Error)]
+pub enum PluginError {
+    /// The plugin is not installed.
+    #[error("plugin library {0} could not be found")]
+    NotFound(String),
+    #[error("failed to open plugin library: {0}")]
+    Open(#[source] libloading::Error),
+    /// The library does not export a symbol every plugin must export.
+    #[error("plugin library does not export {0}")]
+    MissingEntryPoint(&'static str),
+    /// The plugin was built against an incompatible version of this crate.
+    #[error("plugin ABI version mismatch: library uses version {found}, expected {expected}")]
+    VersionMismatch { found: u32, expected: u32 },
+    #[error("plugin ABI layout mismatch: library reports {found:?}, expected {expected:?}")]
+    LayoutMismatch {
+        found: PluginAbi,
+        expected: PluginAbi,
+    },
+    /// The `on_plugin_load` hook of the plugin returned an error.
+    #[error("plugin {name} failed: {message}")]
+    InitFailed { name: String, message: String },
+    #[error("plugin panicked in {0}")]
+    Panicked(&'static str),
+    #[error("plugin {name} does not support the {orientation:?} orientation")]
+    UnsupportedOrientation {
+        name: String,
+        orientation: Orientation,
+    },
+    #[error("plugin {name} does not support the {size:?} size")]
+    UnsupportedSize { name: String, size: Size },
+    #[error("no plugin with handle {0:?} is loaded")]
+    UnknownPlugin(PluginId),
+    /// The plugin cannot go to the requested lifecycle state from its current one.
+    #[error("plugin {name} cannot go from {from:?} to {to:?}")]
+    InvalidTransition {
+        name: String,
+        from: PluginState,
+        to: PluginState,
+    },
+    /// Plugin settings could not be serialized or deserialized.
+    #[error("invalid plugin settings: {0}")]
+    Settings(String),
+    #[error("invalid plugin layout: {0}")]
+    Layout(String),
+    #[error("invalid plugin manifest: {0}")]
+    Manifest(#[from] toml::de::Error),
+    #[error("failed to watch plugin library: {0}")]
+    Watcher(#[from] notify::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
@@ -0,0 +1,66 @@
+// SPDX-License-Identifier: GPL-3.0-only
+//! C view of the plugin ABI, used to generate `include/cosmic_plugin.h` with
+//! `cbindgen --output include/cosmic_plugin.h`.
+//!
+//! `thin_trait_object` generates [`PluginVtable`] from the `Plugin` trait, which
+//! `cbindgen` cannot see, so its layout is mirrored here and checked at
+//! compile time.
+use crate::{
+    ByteSink, ByteSlice, PluginStatus, PluginVtable, Position, RawHostEvent, RawSettings, Size,
+};
+use std::ffi::c_void;
+
+/// The dispatch table of a plugin object, in declaration order of the `Plugin`
+/// trait methods followed by `drop`.
+///
+/// The host only ever calls the `_`-prefixed entries. The remaining entries use
+/// the Rust ABI and are opaque here; plugins written in other languages must
+/// still point them at a function, e.g. one which aborts, as they may not be
+/// null.
+#[repr(C)]
+pub struct CPluginVtable {
+    pub _applet: unsafe extern "C" fn(*mut c_void, *mut *mut gtk4_sys::GtkBox) -> PluginStatus,
+    pub _set_size: unsafe extern "C" fn(*mut c_void, Size) -> PluginStatus,
+    pub _set_position: unsafe extern "C" fn(*mut c_void, Position) -> PluginStatus,
+    pub _css_provider:
+        unsafe extern "C" fn(*mut c_void, *mut *mut gtk4_sys::GtkCssProvider) -> PluginStatus,
+    pub _on_plugin_load: unsafe extern "C" fn(*mut c_void, *const ByteSink) -> PluginStatus,
+    pub _on_plugin_unload: unsafe extern "C" fn(*mut c_void) -> PluginStatus,
+    pub _save_state: unsafe extern "C" fn(*mut c_void, *const ByteSink) -> PluginStatus,
+    pub _restore_state: unsafe extern "C" fn(*mut c_void, *const ByteSlice) -> PluginStatus,
+    pub _state_version: unsafe extern "C" fn(*mut c_void, *mut u32) -> PluginStatus,
+    pub _on_suspend: unsafe extern "C" fn(*mut c_void) -> PluginStatus,
+    pub _on_resume: unsafe extern "C" fn(*mut c_void) -> PluginStatus,
+    pub _on_settings_changed: unsafe extern "C" fn(*mut c_void, *const RawSettings) -> PluginStatus,
+    pub _on_host_event: unsafe extern "C" fn(*mut c_void, *const RawHostEvent) -> PluginStatus,
+    pub _settings_schema: unsafe extern "C" fn(*mut c_void, *const ByteSink) -> PluginStatus,
+    pub applet: unsafe extern "C" fn(),
+    pub css_provider: unsafe extern "C" fn(),
+    pub set_size: unsafe extern "C" fn(),
+    pub set_position: unsafe extern "C" fn(),
+    pub on_plugin_load: unsafe extern "C" fn(),
+    pub on_plugin_unload: unsafe extern "C" fn(),
+    pub save_state: unsafe extern "C" fn(),
+    pub restore_state: unsafe extern "C" fn(),
+    pub state_version: unsafe extern "C" fn(),
+    pub on_suspend: unsafe extern "C" fn(),
+    pub on_resume: unsafe extern "C" fn(),
+    pub on_settings_changed: unsafe extern "C" fn(),
+    pub on_host_event: unsafe extern "C" fn(),
+    pub settings_schema: unsafe extern "C" fn(),
+    /// Free the plugin object.
+    pub drop: unsafe extern "C" fn(*mut c_void),
+}
+
+/// A plugin object. The pointer returned by `_plugin_create` must point to an
+/// allocation starting with this header; the rest of it is owned by the plugin.
+/// `_plugin_create` returns null if the plugin could not be created.
+#[repr(C)]
+pub struct CPlugin {
+    pub vtable: *const CPluginVtable,
+}
+
+const _: () = assert!(
+    std::mem::size_of::<CPluginVtable>() == std::mem::size_of::<PluginVtable>()
+        && std::mem::align_of::<CPluginVtable>() == std::mem::align_of::<PluginVtable>()
+);
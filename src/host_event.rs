@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: GPL-3.0-only
+//! Changes to the environment of the panel, sent from the host to plugins.
+use crate::ByteSlice;
+use serde::{Deserialize, Serialize};
+
+pub const HOST_EVENT_THEME: u32 = 1;
+pub const HOST_EVENT_SCALE: u32 = 2;
+pub const HOST_EVENT_PANEL_VISIBILITY: u32 = 3;
+pub const HOST_EVENT_MONITOR: u32 = 4;
+pub const HOST_EVENT_SESSION_LOCK: u32 = 5;
+
+/// A change to the environment of the panel, passed to
+/// [`Plugin::on_host_event`](crate::Plugin::on_host_event) by
+/// [`PluginManager::send_event`](crate::PluginManager::send_event).
+///
+/// Events cross the library boundary as their kind, one of the `HOST_EVENT_*`
+/// constants, along with the event serialized with ron. New events get a new
+/// kind, so plugins built against an older version ignore them instead of
+/// breaking.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum HostEvent {
+    /// The GTK theme or the dark mode preference changed.
+    Theme { name: String, dark: bool },
+    /// The scale factor of the monitor showing the panel changed.
+    Scale(f64),
+    /// The panel was shown or hidden, e.g. by autohide.
+    PanelVisibility { visible: bool },
+    /// The panel moved to another monitor, identified by its connector name.
+    Monitor { connector: String },
+    /// The session was locked or unlocked.
+    SessionLock { locked: bool },
+}
+
+impl HostEvent {
+    pub fn kind(&self) -> u32 {
+        match self {
+            Self::Theme { .. } => HOST_EVENT_THEME,
+            Self::Scale(_) => HOST_EVENT_SCALE,
+            Self::PanelVisibility { .. } => HOST_EVENT_PANEL_VISIBILITY,
+            Self::Monitor { .. } => HOST_EVENT_MONITOR,
+            Self::SessionLock { .. } => HOST_EVENT_SESSION_LOCK,
+        }
+    }
+}
+
+/// A [`HostEvent`] as passed to `_on_host_event`: its kind, one of the
+/// `HOST_EVENT_*` constants, and the event serialized with ron.
+#[repr(C)]
+pub struct RawHostEvent {
+    pub kind: u32,
+    pub data: ByteSlice,
+}
+
+/// Record the latest event of each kind, which is replayed to plugins loaded
+/// afterwards.
+pub(crate) fn remember(environment: &mut Vec<HostEvent>, event: &HostEvent) {
+    match environment.iter_mut().find(|e| e.kind() == event.kind()) {
+        Some(e) => *e = event.clone(),
+        None => environment.push(event.clone()),
+    }
+}
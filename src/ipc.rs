@@ -0,0 +1,248 @@
+// SPDX-License-Identifier: GPL-3.0-only
+//! Protocol between the [`PluginManager`](crate::PluginManager) and
+//! `cosmic-plugin-host`, which runs a single plugin in its own process so a
+//! crashing plugin cannot take the dock down with it. Messages are serialized
+//! with ron, one per line.
+//!
+//! The host cannot hand its widgets to the dock, so it describes the applet
+//! with a [`UiNode`] tree which the dock turns into proxy widgets. Clicks on
+//! proxy buttons are forwarded to the host.
+use crate::{HostEvent, Position, SettingsSchema, Size};
+use gtk4::prelude::*;
+use gtk4::Orientation;
+use log::error;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io::{self, ErrorKind, Read, Write};
+use std::os::unix::io::RawFd;
+use std::rc::Rc;
+
+/// Name of the host binary, looked up in `PATH` unless
+/// [`PluginManager::set_host_binary`](crate::PluginManager::set_host_binary)
+/// was used.
+pub const HOST_BINARY: &str = "cosmic-plugin-host";
+
+/// File descriptor of the socket connected to the dock in the host process.
+pub const HOST_SOCKET_FD: RawFd = 3;
+
+/// Sent from the dock to the host.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum HostRequest {
+    SetSize(Size),
+    SetPosition(Position),
+    /// The proxy of the button at this path of the applet was clicked.
+    Activate(Vec<usize>),
+    /// Must be answered with [`HostMessage::Pong`], the host is restarted if
+    /// its main loop is blocked for too long to answer.
+    Ping,
+    /// The plugin was disabled, see [`PluginManager::set_enabled`](crate::PluginManager::set_enabled).
+    Suspend,
+    Resume,
+    /// See [`PluginManager::send_event`](crate::PluginManager::send_event).
+    Event(HostEvent),
+    Shutdown,
+}
+
+/// Sent from the host to the dock.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum HostMessage {
+    /// The applet changed.
+    Ui(UiNode),
+    /// The stylesheet of the plugin.
+    Css(String),
+    /// The settings schema of the plugin changed.
+    Schema(Option<SettingsSchema>),
+    /// The plugin could not be loaded or failed, the host exits afterwards.
+    Error(String),
+    Pong,
+}
+
+/// Description of a widget of an applet.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UiNode {
+    pub kind: UiKind,
+    pub css_classes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum UiKind {
+    Box {
+        vertical: bool,
+        spacing: i32,
+        children: Vec<UiNode>,
+    },
+    Label {
+        text: String,
+    },
+    Button {
+        label: Option<String>,
+        icon: Option<String>,
+        child: Option<Box<UiNode>>,
+    },
+    Image {
+        icon: Option<String>,
+    },
+    /// A widget which cannot be described, shown as an empty box.
+    Unsupported,
+}
+
+impl UiNode {
+    /// Describe a widget and its children, in the host process.
+    pub fn describe(widget: &gtk4::Widget) -> Self {
+        let kind = if let Some(b) = widget.downcast_ref::<gtk4::Box>() {
+            UiKind::Box {
+                vertical: b.orientation() == Orientation::Vertical,
+                spacing: b.spacing(),
+                children: children(widget).iter().map(Self::describe).collect(),
+            }
+        } else if let Some(l) = widget.downcast_ref::<gtk4::Label>() {
+            UiKind::Label {
+                text: l.text().to_string(),
+            }
+        } else if let Some(b) = widget.downcast_ref::<gtk4::Button>() {
+            let label = b.label().map(|s| s.to_string());
+            let icon = b.icon_name().map(|s| s.to_string());
+            // the child of a button with a label or icon is created by gtk
+            let child = if label.is_none() && icon.is_none() {
+                b.child().map(|c| Box::new(Self::describe(&c)))
+            } else {
+                None
+            };
+            UiKind::Button { label, icon, child }
+        } else if let Some(i) = widget.downcast_ref::<gtk4::Image>() {
+            UiKind::Image {
+                icon: i.icon_name().map(|s| s.to_string()),
+            }
+        } else {
+            UiKind::Unsupported
+        };
+        Self {
+            kind,
+            css_classes: widget.css_classes().iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    /// Find the widget at a path of child indices below `widget`, in the host
+    /// process.
+    pub fn find(widget: &gtk4::Widget, path: &[usize]) -> Option<gtk4::Widget> {
+        path.iter().try_fold(widget.clone(), |widget, &i| {
+            children(&widget).into_iter().nth(i)
+        })
+    }
+
+    /// Build a proxy widget from the description, in the dock process.
+    /// `activate` is called with the path of a button when its proxy is
+    /// clicked.
+    pub fn build(&self, path: &mut Vec<usize>, activate: &Rc<dyn Fn(Vec<usize>)>) -> gtk4::Widget {
+        let widget: gtk4::Widget = match &self.kind {
+            UiKind::Box {
+                vertical,
+                spacing,
+                children,
+            } => {
+                let orientation = if *vertical {
+                    Orientation::Vertical
+                } else {
+                    Orientation::Horizontal
+                };
+                let b = gtk4::Box::new(orientation, *spacing);
+                for (i, child) in children.iter().enumerate() {
+                    path.push(i);
+                    b.append(&child.build(path, activate));
+                    path.pop();
+                }
+                b.upcast()
+            }
+            UiKind::Label { text } => gtk4::Label::new(Some(text)).upcast(),
+            UiKind::Button { label, icon, child } => {
+                let button = gtk4::Button::new();
+                if let Some(label) = label {
+                    button.set_label(label);
+                }
+                if let Some(icon) = icon {
+                    button.set_icon_name(icon);
+                }
+                if let Some(child) = child {
+                    path.push(0);
+                    button.set_child(Some(&child.build(path, activate)));
+                    path.pop();
+                }
+                let activate = activate.clone();
+                let path = path.clone();
+                button.connect_clicked(move |_| activate(path.clone()));
+                button.upcast()
+            }
+            UiKind::Image { icon } => {
+                let image = gtk4::Image::new();
+                image.set_icon_name(icon.as_deref());
+                image.upcast()
+            }
+            UiKind::Unsupported => gtk4::Box::new(Orientation::Horizontal, 0).upcast(),
+        };
+        for class in &self.css_classes {
+            widget.add_css_class(class);
+        }
+        widget
+    }
+}
+
+fn children(widget: &gtk4::Widget) -> Vec<gtk4::Widget> {
+    let mut children = Vec::new();
+    let mut child = widget.first_child();
+    while let Some(c) = child {
+        child = c.next_sibling();
+        children.push(c);
+    }
+    children
+}
+
+/// Collects data read from a socket and splits it into messages.
+#[derive(Debug, Default)]
+pub struct MessageReader {
+    buf: Vec<u8>,
+}
+
+impl MessageReader {
+    /// Read once from a readable stream and return the messages completed by
+    /// the data read, or `None` if the stream was closed. Malformed messages
+    /// are logged and skipped, and a non-blocking stream without data yields
+    /// no messages.
+    pub fn read<T: DeserializeOwned>(
+        &mut self,
+        stream: &mut impl Read,
+    ) -> io::Result<Option<Vec<T>>> {
+        let mut chunk = [0; 4096];
+        let n = match stream.read(&mut chunk) {
+            Ok(n) => n,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {
+                return Ok(Some(Vec::new()))
+            }
+            Err(e) => return Err(e),
+        };
+        if n == 0 {
+            return Ok(None);
+        }
+        self.buf.extend_from_slice(&chunk[..n]);
+        let mut messages = Vec::new();
+        while let Some(end) = self.buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=end).collect();
+            match ron::de::from_bytes(&line[..end]) {
+                Ok(message) => messages.push(message),
+                Err(e) => error!("invalid message from the plugin host: {}", e),
+            }
+        }
+        Ok(Some(messages))
+    }
+}
+
+/// Serialize a single message, including its terminating newline.
+pub fn encode_message<T: Serialize>(message: &T) -> io::Result<Vec<u8>> {
+    let mut line = ron::to_string(message)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))?;
+    line.push('\n');
+    Ok(line.into_bytes())
+}
+
+/// Write a single message to a stream.
+pub fn write_message<T: Serialize>(stream: &mut impl Write, message: &T) -> io::Result<()> {
+    stream.write_all(&encode_message(message)?)
+}
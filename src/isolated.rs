@@ -0,0 +1,498 @@
+// SPDX-License-Identifier: GPL-3.0-only
+//! Supervision of plugins running in a `cosmic-plugin-host` process, see
+//! [`crate::ipc`].
+use crate::ipc::{encode_message, HostMessage, HostRequest, MessageReader, HOST_SOCKET_FD};
+use crate::{
+    HostEvent, PluginEvent, PluginHealth, PluginId, PluginState, Position, RestartPolicy, Result,
+    SettingsSchema, Size,
+};
+use gtk4::prelude::*;
+use gtk4::{glib, CssProvider, Orientation};
+use log::{debug, error};
+use std::cell::RefCell;
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+/// Delay before the first restart of a crashed host.
+pub const RESTART_BACKOFF_MIN: Duration = Duration::from_secs(1);
+/// The restart delay doubles with every crash up to this limit.
+pub const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(60);
+/// A host which ran this long before crashing is restarted after the minimum
+/// delay again.
+pub const RESTART_BACKOFF_RESET: Duration = Duration::from_secs(30);
+/// Time a host gets to exit after being asked to before it is killed.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+/// Interval in which a host which was asked to exit is checked for having
+/// exited.
+const REAP_INTERVAL: Duration = Duration::from_millis(50);
+
+struct HostProcess {
+    child: Child,
+    stream: UnixStream,
+    source: Option<glib::SourceId>,
+    reader: MessageReader,
+    started: Instant,
+    /// set once the host loaded the plugin, before which it is not pinged
+    ready: bool,
+    /// time of the last ping which was not answered yet
+    awaiting_pong: Option<Instant>,
+    /// rest of a message which the socket only took part of
+    unsent: Vec<u8>,
+}
+
+impl HostProcess {
+    /// Queue a message after the rest of the previous one and write as much
+    /// as the socket takes without blocking. The message is dropped if the
+    /// rest of the previous one does not fit, so a host which stopped reading
+    /// misses messages instead of freezing the dock, but never receives a
+    /// torn one.
+    fn write(&mut self, message: &[u8]) -> io::Result<bool> {
+        self.flush()?;
+        if !self.unsent.is_empty() {
+            return Ok(false);
+        }
+        self.unsent.extend_from_slice(message);
+        self.flush()?;
+        Ok(true)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.unsent.is_empty() {
+            match self.stream.write(&self.unsent) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.unsent.drain(..n);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) struct IsolatedPlugin {
+    pub(crate) id: PluginId,
+    pub(crate) name: String,
+    /// passed to the host, names the settings file of the instance
+    pub(crate) instance: String,
+    pub(crate) lib_path: PathBuf,
+    host_binary: PathBuf,
+    /// replaced by the proxy of the applet whenever the host sends one
+    pub(crate) applet: gtk4::Box,
+    pub(crate) css_provider: CssProvider,
+    /// last settings schema sent by the host
+    pub(crate) schema: Option<SettingsSchema>,
+    process: Option<HostProcess>,
+    size: Option<Size>,
+    position: Position,
+    backoff: Duration,
+    restart: Option<glib::SourceId>,
+    shut_down: bool,
+    pub(crate) policy: RestartPolicy,
+    failures: u32,
+    pub(crate) health: PluginHealth,
+    /// events which have not been returned by `PluginManager::poll_events` yet
+    pub(crate) events: Vec<PluginEvent>,
+    watchdog: Option<Duration>,
+    ping: Option<glib::SourceId>,
+    pub(crate) suspended: bool,
+    /// latest host event of each kind, sent to every new host
+    environment: Vec<HostEvent>,
+}
+
+impl IsolatedPlugin {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn spawn(
+        id: PluginId,
+        name: String,
+        instance: String,
+        lib_path: PathBuf,
+        host_binary: PathBuf,
+        policy: RestartPolicy,
+        watchdog: Option<Duration>,
+        size: Option<Size>,
+        position: Position,
+        environment: Vec<HostEvent>,
+    ) -> Result<Rc<RefCell<Self>>> {
+        let plugin = Rc::new(RefCell::new(Self {
+            id,
+            name,
+            instance,
+            lib_path,
+            host_binary,
+            applet: gtk4::Box::new(Orientation::Horizontal, 0),
+            css_provider: CssProvider::new(),
+            schema: None,
+            process: None,
+            size,
+            position,
+            backoff: RESTART_BACKOFF_MIN,
+            restart: None,
+            shut_down: false,
+            policy,
+            failures: 0,
+            health: PluginHealth::Running,
+            events: Vec::new(),
+            watchdog,
+            ping: None,
+            suspended: false,
+            environment,
+        }));
+        Self::start(&plugin)?;
+        Ok(plugin)
+    }
+
+    fn start(this: &Rc<RefCell<Self>>) -> Result<()> {
+        let mut plugin = this.borrow_mut();
+        let (stream, host_stream) = UnixStream::pair()?;
+        let host_fd = host_stream.as_raw_fd();
+        let mut command = Command::new(&plugin.host_binary);
+        command.arg(&plugin.lib_path).arg(&plugin.instance);
+        unsafe {
+            command.pre_exec(move || {
+                // dup2 keeps the close-on-exec flag if the descriptor already has the number
+                let res = if host_fd == HOST_SOCKET_FD {
+                    libc::fcntl(host_fd, libc::F_SETFD, 0)
+                } else {
+                    libc::dup2(host_fd, HOST_SOCKET_FD)
+                };
+                if res == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = command.spawn()?;
+        drop(host_stream);
+        stream.set_nonblocking(true)?;
+        debug!("started plugin host {} for {}", child.id(), plugin.name);
+
+        let weak = Rc::downgrade(this);
+        let source = glib::unix_fd_add_local(
+            stream.as_raw_fd(),
+            glib::IOCondition::IN | glib::IOCondition::HUP | glib::IOCondition::ERR,
+            move |_, _| match weak.upgrade() {
+                Some(plugin) => Self::on_readable(&plugin),
+                None => glib::Continue(false),
+            },
+        );
+        plugin.process = Some(HostProcess {
+            child,
+            stream,
+            source: Some(source),
+            reader: MessageReader::default(),
+            started: Instant::now(),
+            ready: false,
+            awaiting_pong: None,
+            unsent: Vec::new(),
+        });
+        if let Some(threshold) = plugin.watchdog {
+            let weak = Rc::downgrade(this);
+            plugin.ping = Some(glib::timeout_add_local(threshold, move || {
+                match weak.upgrade() {
+                    Some(plugin) => plugin.borrow_mut().on_watchdog(),
+                    None => glib::Continue(false),
+                }
+            }));
+        }
+        let health = if plugin.failures > 0 {
+            PluginHealth::Degraded
+        } else {
+            PluginHealth::Running
+        };
+        plugin.set_health(health);
+        // bring the new host up to date with the panel
+        if let Some(size) = plugin.size {
+            plugin.send(&HostRequest::SetSize(size));
+        }
+        let position = plugin.position;
+        plugin.send(&HostRequest::SetPosition(position));
+        if plugin.suspended {
+            plugin.send(&HostRequest::Suspend);
+        }
+        for event in plugin.environment.clone() {
+            plugin.send(&HostRequest::Event(event));
+        }
+        Ok(())
+    }
+
+    fn on_readable(this: &Rc<RefCell<Self>>) -> glib::Continue {
+        let mut plugin = this.borrow_mut();
+        let read = match plugin.process.as_mut() {
+            Some(p) => p.reader.read::<HostMessage>(&mut p.stream),
+            None => return glib::Continue(false),
+        };
+        match read {
+            Ok(Some(messages)) => {
+                for message in messages {
+                    plugin.handle(this, message);
+                }
+                glib::Continue(true)
+            }
+            Ok(None) => {
+                plugin.on_exit(this);
+                glib::Continue(false)
+            }
+            Err(e) => {
+                error!(
+                    "lost connection to the host of plugin {}: {}",
+                    plugin.name, e
+                );
+                plugin.on_exit(this);
+                glib::Continue(false)
+            }
+        }
+    }
+
+    fn handle(&mut self, this: &Rc<RefCell<Self>>, message: HostMessage) {
+        if let Some(p) = self.process.as_mut() {
+            p.ready = true;
+        }
+        match message {
+            HostMessage::Ui(node) => {
+                while let Some(child) = self.applet.first_child() {
+                    self.applet.remove(&child);
+                }
+                let weak = Rc::downgrade(this);
+                let activate: Rc<dyn Fn(Vec<usize>)> = Rc::new(move |path| {
+                    if let Some(plugin) = weak.upgrade() {
+                        plugin.borrow_mut().send(&HostRequest::Activate(path));
+                    }
+                });
+                self.applet.append(&node.build(&mut Vec::new(), &activate));
+            }
+            HostMessage::Css(css) => self.css_provider.load_from_data(css.as_bytes()),
+            HostMessage::Schema(schema) => self.schema = schema,
+            HostMessage::Error(e) => error!("plugin {} failed in its host: {}", self.name, e),
+            HostMessage::Pong => {
+                if let Some(p) = self.process.as_mut() {
+                    p.awaiting_pong = None;
+                }
+            }
+        }
+    }
+
+    /// Ping the host, or kill it if it did not answer the previous ping
+    /// because its main loop is blocked.
+    fn on_watchdog(&mut self) -> glib::Continue {
+        let process = match self.process.as_mut() {
+            Some(p) => p,
+            None => {
+                self.ping = None;
+                return glib::Continue(false);
+            }
+        };
+        if !process.ready {
+            return glib::Continue(true);
+        }
+        match process.awaiting_pong {
+            Some(sent) => {
+                error!(
+                    "host of plugin {} did not respond for {:?} and is killed",
+                    self.name,
+                    sent.elapsed()
+                );
+                // the closed socket is noticed like any other exit
+                let _ = process.child.kill();
+                self.ping = None;
+                self.set_health(PluginHealth::Degraded);
+                glib::Continue(false)
+            }
+            None => {
+                process.awaiting_pong = Some(Instant::now());
+                self.send(&HostRequest::Ping);
+                glib::Continue(true)
+            }
+        }
+    }
+
+    fn set_health(&mut self, health: PluginHealth) {
+        if self.health != health {
+            self.health = health;
+            self.events.push(PluginEvent::HealthChanged {
+                id: self.id,
+                name: self.name.clone(),
+                health,
+            });
+        }
+    }
+
+    /// Reap the exited host and restart it if its policy allows. The watch on
+    /// its socket is removed by returning from its callback.
+    fn on_exit(&mut self, this: &Rc<RefCell<Self>>) {
+        let mut process = match self.process.take() {
+            Some(p) => p,
+            None => return,
+        };
+        process.source.take();
+        if let Some(ping) = self.ping.take() {
+            ping.remove();
+        }
+        let _ = process.child.kill();
+        let failed = match process.child.wait() {
+            Ok(status) => {
+                error!("host of plugin {} exited with {}", self.name, status);
+                !status.success()
+            }
+            Err(e) => {
+                error!("failed to reap the host of plugin {}: {}", self.name, e);
+                true
+            }
+        };
+        if process.started.elapsed() >= RESTART_BACKOFF_RESET {
+            self.backoff = RESTART_BACKOFF_MIN;
+        }
+        self.on_stopped(Rc::downgrade(this), failed);
+    }
+
+    /// Schedule a restart of the stopped host or disable the plugin,
+    /// depending on its restart policy.
+    fn on_stopped(&mut self, weak: Weak<RefCell<Self>>, failed: bool) {
+        if self.shut_down {
+            return;
+        }
+        if failed {
+            self.failures += 1;
+        }
+        if !self.policy.should_restart(self.failures, failed) {
+            error!("plugin {} is not restarted again", self.name);
+            self.set_health(PluginHealth::Disabled);
+            return;
+        }
+        self.set_health(PluginHealth::Faulted);
+        debug!("restarting plugin {} in {:?}", self.name, self.backoff);
+        self.restart = Some(glib::timeout_add_local_once(self.backoff, move || {
+            let plugin = match weak.upgrade() {
+                Some(p) => p,
+                None => return,
+            };
+            plugin.borrow_mut().restart = None;
+            if let Err(e) = Self::start(&plugin) {
+                let mut p = plugin.borrow_mut();
+                error!("failed to restart the host of plugin {}: {}", p.name, e);
+                p.on_stopped(Rc::downgrade(&plugin), true);
+            }
+        }));
+        self.backoff = (self.backoff * 2).min(RESTART_BACKOFF_MAX);
+    }
+
+    pub(crate) fn send(&mut self, request: &HostRequest) {
+        let p = match self.process.as_mut() {
+            Some(p) => p,
+            None => return,
+        };
+        match encode_message(request).and_then(|message| p.write(&message)) {
+            Ok(true) => {}
+            Ok(false) => debug!(
+                "dropped {:?} as the host of plugin {} is not reading",
+                request, self.name
+            ),
+            Err(e) => debug!(
+                "failed to send {:?} to the host of plugin {}: {}",
+                request, self.name, e
+            ),
+        }
+    }
+
+    pub(crate) fn set_size(&mut self, size: Size) {
+        self.size = Some(size);
+        self.send(&HostRequest::SetSize(size));
+    }
+
+    pub(crate) fn set_position(&mut self, position: Position) {
+        self.position = position;
+        self.send(&HostRequest::SetPosition(position));
+    }
+
+    pub(crate) fn send_event(&mut self, event: &HostEvent) {
+        crate::host_event::remember(&mut self.environment, event);
+        self.send(&HostRequest::Event(event.clone()));
+    }
+
+    pub(crate) fn state(&self) -> PluginState {
+        match &self.process {
+            None if self.shut_down => PluginState::Unloading,
+            None if self.health == PluginHealth::Disabled => PluginState::Disabled,
+            None => PluginState::Faulted,
+            Some(p) if !p.ready => PluginState::Loading,
+            Some(_) if self.suspended => PluginState::Suspended,
+            Some(_) => PluginState::Active,
+        }
+    }
+
+    /// Hide the proxy applet and suspend the plugin in its host, or resume it.
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        if self.suspended != enabled {
+            return;
+        }
+        self.suspended = !enabled;
+        self.applet.set_visible(enabled);
+        self.send(if enabled {
+            &HostRequest::Resume
+        } else {
+            &HostRequest::Suspend
+        });
+    }
+
+    /// Start the host of a plugin which failed again, resetting its failure
+    /// count. A degraded plugin whose host is running is marked as running.
+    pub(crate) fn reenable(this: &Rc<RefCell<Self>>) -> Result<()> {
+        {
+            let mut plugin = this.borrow_mut();
+            if plugin.shut_down {
+                return Ok(());
+            }
+            plugin.failures = 0;
+            plugin.backoff = RESTART_BACKOFF_MIN;
+            if plugin.process.is_some() {
+                plugin.set_health(PluginHealth::Running);
+                return Ok(());
+            }
+            if let Some(restart) = plugin.restart.take() {
+                restart.remove();
+            }
+        }
+        Self::start(this)
+    }
+
+    /// Ask the host to exit and stop restarting it. The host is reaped from
+    /// the main loop, and killed if it did not exit in time.
+    pub(crate) fn shutdown(&mut self) {
+        self.shut_down = true;
+        if let Some(restart) = self.restart.take() {
+            restart.remove();
+        }
+        if let Some(ping) = self.ping.take() {
+            ping.remove();
+        }
+        self.send(&HostRequest::Shutdown);
+        let mut process = match self.process.take() {
+            Some(p) => p,
+            None => return,
+        };
+        if let Some(source) = process.source.take() {
+            source.remove();
+        }
+        // closing the socket makes the host exit even if it missed the request
+        drop(process.stream);
+        let mut child = process.child;
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        glib::timeout_add_local(REAP_INTERVAL, move || {
+            if matches!(child.try_wait(), Ok(None)) && Instant::now() < deadline {
+                return glib::Continue(true);
+            }
+            // does nothing if the host already exited
+            let _ = child.kill();
+            let _ = child.wait();
+            glib::Continue(false)
+        });
+    }
+}
@@ -0,0 +1,276 @@
+// SPDX-License-Identifier: GPL-3.0-only
+//! Arrangement of plugin instances on the panel.
+use crate::{PluginError, PluginId, RestartPolicy, Result};
+use gtk4::glib;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Directory relative to `$XDG_CONFIG_HOME` in which layouts are stored, as
+/// `<name>.ron`. Each of them is a profile which can be switched to with
+/// [`PluginManager::switch_profile`](crate::PluginManager::switch_profile).
+pub const LAYOUT_DIR: &str = "cosmic/plugin-layouts";
+
+/// Group of applets on the panel, at its start, center or end.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    Start,
+    Center,
+    End,
+}
+
+impl Default for Alignment {
+    fn default() -> Self {
+        Self::Start
+    }
+}
+
+/// A plugin instance placed on the panel.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LayoutEntry {
+    /// Name of the plugin library.
+    pub plugin: String,
+    /// Name of the instance, which names its settings file.
+    pub instance: String,
+    #[serde(default)]
+    pub alignment: Alignment,
+    #[serde(default)]
+    pub overrides: InstanceOverrides,
+}
+
+/// How an instance is run, instead of the defaults of the manager.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InstanceOverrides {
+    /// Run the plugin in a separate host process.
+    #[serde(default)]
+    pub isolated: bool,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub restart_policy: Option<RestartPolicy>,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Default for InstanceOverrides {
+    fn default() -> Self {
+        Self {
+            isolated: false,
+            enabled: true,
+            restart_policy: None,
+        }
+    }
+}
+
+/// The plugin instances on the panel, in order within each alignment group.
+/// Applied with [`PluginManager::apply_layout`](crate::PluginManager::apply_layout).
+///
+/// ```ron
+/// (
+///     entries: [
+///         (plugin: "cosmic_launcher", instance: "cosmic_launcher"),
+///         (plugin: "cosmic_clock", instance: "cosmic_clock", alignment: Center),
+///         (
+///             plugin: "cosmic_clock",
+///             instance: "cosmic_clock-2",
+///             alignment: End,
+///             overrides: (isolated: true),
+///         ),
+///     ],
+/// )
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Layout {
+    pub entries: Vec<LayoutEntry>,
+}
+
+impl Layout {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        ron::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| PluginError::Layout(e.to_string()))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let layout = ron::ser::to_string_pretty(self, Default::default())
+            .map_err(|e| PluginError::Layout(e.to_string()))?;
+        if let Some(dir) = path.as_ref().parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, layout)?;
+        Ok(())
+    }
+
+    /// Entries of an alignment group, in order.
+    pub fn group(&self, alignment: Alignment) -> impl Iterator<Item = &LayoutEntry> {
+        self.entries
+            .iter()
+            .filter(move |e| e.alignment == alignment)
+    }
+}
+
+/// What [`PluginManager::apply_layout`](crate::PluginManager::apply_layout)
+/// changed.
+#[derive(Debug, Default)]
+pub struct LayoutChanges {
+    /// Instances which were loaded, their applets must be added.
+    pub loaded: Vec<PluginId>,
+    /// Instances which were removed with their applets, which must be removed
+    /// before the next call to `poll_events`. Instances which failed before
+    /// have no applet shown and are left out.
+    pub unloaded: Vec<(PluginId, gtk4::Box)>,
+    /// Instances of the layout which could not be loaded, by instance name.
+    pub failed: Vec<(String, PluginError)>,
+}
+
+/// Move `id` to position `index` of the `alignment` group of `slots`, or to
+/// the end of the group if it is shorter. Returns the position in the group
+/// if the slot of `id` changed.
+pub(crate) fn move_slot(
+    slots: &mut Vec<(PluginId, Alignment)>,
+    id: PluginId,
+    alignment: Alignment,
+    index: usize,
+) -> Result<Option<usize>> {
+    let current = slots
+        .iter()
+        .position(|(i, _)| *i == id)
+        .ok_or(PluginError::UnknownPlugin(id))?;
+    let (_, previous) = slots.remove(current);
+    let previous_index = slots[..current]
+        .iter()
+        .filter(|(_, a)| *a == previous)
+        .count();
+    let group: Vec<usize> = slots
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, a))| *a == alignment)
+        .map(|(i, _)| i)
+        .collect();
+    let index = index.min(group.len());
+    let position = match group.get(index) {
+        Some(&i) => i,
+        None => group.last().map_or(slots.len(), |&i| i + 1),
+    };
+    slots.insert(position, (id, alignment));
+    Ok((previous != alignment || previous_index != index).then(|| index))
+}
+
+/// Names of the layout profiles in the config directory, sorted. Profiles are
+/// written by [`PluginManager::save_profile`](crate::PluginManager::save_profile)
+/// or by hand.
+pub fn profiles() -> Vec<String> {
+    let dir = glib::user_config_dir().join(LAYOUT_DIR);
+    let mut profiles: Vec<String> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().map_or(false, |e| e == "ron"))
+            .filter_map(|p| Some(p.file_stem()?.to_str()?.to_string()))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    profiles.sort();
+    profiles
+}
+
+/// Path of the layout named `name` in the config directory.
+pub fn layout_path(name: &str) -> PathBuf {
+    glib::user_config_dir()
+        .join(LAYOUT_DIR)
+        .join(format!("{}.ron", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Alignment::*;
+
+    fn slots() -> Vec<(PluginId, Alignment)> {
+        vec![
+            (PluginId(0), Start),
+            (PluginId(1), Start),
+            (PluginId(2), Center),
+            (PluginId(3), Start),
+            (PluginId(4), End),
+        ]
+    }
+
+    fn group(slots: &[(PluginId, Alignment)], alignment: Alignment) -> Vec<u64> {
+        slots
+            .iter()
+            .filter(|(_, a)| *a == alignment)
+            .map(|(id, _)| id.0)
+            .collect()
+    }
+
+    #[test]
+    fn move_within_group() {
+        let mut slots = slots();
+        assert_eq!(
+            move_slot(&mut slots, PluginId(3), Start, 0).unwrap(),
+            Some(0)
+        );
+        assert_eq!(group(&slots, Start), [3, 0, 1]);
+        assert_eq!(
+            move_slot(&mut slots, PluginId(3), Start, 1).unwrap(),
+            Some(1)
+        );
+        assert_eq!(group(&slots, Start), [0, 3, 1]);
+        assert_eq!(group(&slots, Center), [2]);
+        assert_eq!(group(&slots, End), [4]);
+    }
+
+    #[test]
+    fn move_across_groups() {
+        let mut slots = slots();
+        assert_eq!(move_slot(&mut slots, PluginId(1), End, 0).unwrap(), Some(0));
+        assert_eq!(group(&slots, Start), [0, 3]);
+        assert_eq!(group(&slots, End), [1, 4]);
+        assert_eq!(move_slot(&mut slots, PluginId(2), End, 1).unwrap(), Some(1));
+        assert_eq!(group(&slots, Center), Vec::<u64>::new());
+        assert_eq!(group(&slots, End), [1, 2, 4]);
+        // into an empty group
+        assert_eq!(
+            move_slot(&mut slots, PluginId(0), Center, 0).unwrap(),
+            Some(0)
+        );
+        assert_eq!(group(&slots, Center), [0]);
+    }
+
+    #[test]
+    fn move_past_end() {
+        let mut slots = slots();
+        assert_eq!(
+            move_slot(&mut slots, PluginId(0), Start, 10).unwrap(),
+            Some(2)
+        );
+        assert_eq!(group(&slots, Start), [1, 3, 0]);
+        assert_eq!(
+            move_slot(&mut slots, PluginId(1), End, 10).unwrap(),
+            Some(1)
+        );
+        assert_eq!(group(&slots, End), [4, 1]);
+    }
+
+    #[test]
+    fn move_to_same_slot() {
+        let mut slots = slots();
+        assert_eq!(move_slot(&mut slots, PluginId(1), Start, 1).unwrap(), None);
+        assert_eq!(move_slot(&mut slots, PluginId(3), Start, 10).unwrap(), None);
+        assert_eq!(move_slot(&mut slots, PluginId(2), Center, 0).unwrap(), None);
+        for alignment in [Start, Center, End] {
+            assert_eq!(group(&slots, alignment), group(&self::slots(), alignment));
+        }
+    }
+
+    #[test]
+    fn move_unknown() {
+        let mut slots = slots();
+        assert!(matches!(
+            move_slot(&mut slots, PluginId(5), Start, 0),
+            Err(PluginError::UnknownPlugin(PluginId(5)))
+        ));
+        assert_eq!(slots, self::slots());
+    }
+}
@@ -0,0 +1,380 @@
+// SPDX-License-Identifier: GPL-3.0-only
+//! Reader for the dynamic linker cache written by `ldconfig`, supporting both
+//! the old libc5 format and the new glibc format, as well as the combined
+//! format which contains both.
+use std::convert::TryInto;
+use std::ffi::{OsStr, OsString};
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+pub const LD_CACHE_PATH: &str = "/etc/ld.so.cache";
+
+/// Directories searched by the dynamic linker after its cache.
+#[cfg(target_pointer_width = "64")]
+pub const DEFAULT_LIB_DIRS: &[&str] = &["/lib64", "/usr/lib64", "/lib", "/usr/lib"];
+#[cfg(not(target_pointer_width = "64"))]
+pub const DEFAULT_LIB_DIRS: &[&str] = &["/lib", "/usr/lib"];
+
+const OLD_MAGIC: &[u8] = b"ld.so-1.7.0";
+const OLD_HEADER_SIZE: usize = 16;
+const OLD_ENTRY_SIZE: usize = 12;
+
+const NEW_MAGIC: &[u8] = b"glibc-ld.so.cache1.1";
+const NEW_HEADER_SIZE: usize = 48;
+const NEW_ENTRY_SIZE: usize = 24;
+const NEW_ALIGN: usize = 8;
+
+const ENDIAN_LITTLE: u8 = 2;
+const ENDIAN_BIG: u8 = 3;
+
+const FLAG_TYPE_MASK: i32 = 0x00ff;
+const FLAG_ELF_LIBC6: i32 = 0x0003;
+const FLAG_ARCH_MASK: i32 = 0xff00;
+
+/// Architecture flags of libraries usable by this process, if known.
+#[cfg(target_arch = "x86_64")]
+const FLAG_ARCH: Option<i32> = Some(0x0300);
+#[cfg(target_arch = "aarch64")]
+const FLAG_ARCH: Option<i32> = Some(0x0a00);
+#[cfg(target_arch = "x86")]
+const FLAG_ARCH: Option<i32> = Some(0x0000);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "x86")))]
+const FLAG_ARCH: Option<i32> = None;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LdCacheEntry {
+    pub flags: i32,
+    /// file name of the library, usually its soname
+    pub key: OsString,
+    pub path: PathBuf,
+}
+
+impl LdCacheEntry {
+    /// Whether the library can be loaded by this process.
+    pub fn is_native(&self) -> bool {
+        self.flags & FLAG_TYPE_MASK == FLAG_ELF_LIBC6
+            && FLAG_ARCH.map_or(true, |arch| self.flags & FLAG_ARCH_MASK == arch)
+    }
+}
+
+/// The parsed contents of `/etc/ld.so.cache`, in the order of preference of
+/// the dynamic linker.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LdCache {
+    pub entries: Vec<LdCacheEntry>,
+}
+
+impl LdCache {
+    pub fn load() -> Result<Self> {
+        Self::from_file(LD_CACHE_PATH)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.starts_with(NEW_MAGIC) {
+            return Self::parse_new(data);
+        }
+        if !data.starts_with(OLD_MAGIC) {
+            return Err(invalid("not a ld.so.cache file."));
+        }
+
+        let nlibs = read_u32(data, OLD_MAGIC.len() + 1)? as usize;
+        let entries_end = nlibs
+            .checked_mul(OLD_ENTRY_SIZE)
+            .and_then(|len| len.checked_add(OLD_HEADER_SIZE))
+            .ok_or_else(|| invalid("ld.so.cache is truncated."))?;
+        // the new format follows the old one in the combined format, glibc
+        // only uses the latter if present.
+        let new_offset = (entries_end + NEW_ALIGN - 1) & !(NEW_ALIGN - 1);
+        if let Some(new) = data.get(new_offset..).filter(|d| d.starts_with(NEW_MAGIC)) {
+            return Self::parse_new(new);
+        }
+
+        let strings = data
+            .get(entries_end..)
+            .ok_or_else(|| invalid("ld.so.cache is truncated."))?;
+        let entries = (0..nlibs)
+            .map(|i| {
+                let offset = OLD_HEADER_SIZE + i * OLD_ENTRY_SIZE;
+                read_entry(data, offset, strings)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { entries })
+    }
+
+    fn parse_new(data: &[u8]) -> Result<Self> {
+        let nlibs = read_u32(data, NEW_MAGIC.len())? as usize;
+        let endianness = *data
+            .get(NEW_MAGIC.len() + 8)
+            .ok_or_else(|| invalid("ld.so.cache is truncated."))?;
+        let native = if cfg!(target_endian = "little") {
+            ENDIAN_LITTLE
+        } else {
+            ENDIAN_BIG
+        };
+        // older versions of ldconfig leave the endianness unset
+        if endianness != 0 && endianness != native {
+            return Err(invalid(
+                "ld.so.cache was written for a different endianness.",
+            ));
+        }
+
+        let entries = (0..nlibs)
+            .map(|i| {
+                let offset = i
+                    .checked_mul(NEW_ENTRY_SIZE)
+                    .and_then(|o| o.checked_add(NEW_HEADER_SIZE))
+                    .ok_or_else(|| invalid("ld.so.cache is truncated."))?;
+                read_entry(data, offset, data)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { entries })
+    }
+
+    /// Find the path of a library usable by this process by its file name.
+    pub fn get<T: AsRef<OsStr>>(&self, filename: T) -> Option<&Path> {
+        self.entries
+            .iter()
+            .find(|e| e.key == filename.as_ref() && e.is_native())
+            .map(|e| e.path.as_path())
+    }
+}
+
+/// Read the flags, key and value of an entry, which share the same layout in
+/// both formats.
+fn read_entry(data: &[u8], offset: usize, strings: &[u8]) -> Result<LdCacheEntry> {
+    let flags = read_u32(data, offset)? as i32;
+    let key = read_str(strings, read_u32(data, offset + 4)? as usize)?;
+    let path = read_str(strings, read_u32(data, offset + 8)? as usize)?;
+    Ok(LdCacheEntry {
+        flags,
+        key: key.to_os_string(),
+        path: PathBuf::from(path),
+    })
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_ne_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid("ld.so.cache is truncated."))
+}
+
+fn read_str(data: &[u8], offset: usize) -> Result<&OsStr> {
+    let s = data
+        .get(offset..)
+        .ok_or_else(|| invalid("ld.so.cache string offset is out of bounds."))?;
+    let len = s
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| invalid("ld.so.cache string is not terminated."))?;
+    Ok(OsStr::from_bytes(&s[..len]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLAG_ARCH_NATIVE: i32 = match FLAG_ARCH {
+        Some(arch) => arch,
+        None => 0,
+    };
+    const NATIVE: i32 = FLAG_ELF_LIBC6 | FLAG_ARCH_NATIVE;
+
+    fn push_u32(data: &mut Vec<u8>, value: u32) {
+        data.extend_from_slice(&value.to_ne_bytes());
+    }
+
+    /// Strings of the entries, and the offsets of their keys and paths.
+    fn strings(entries: &[(i32, &str, &str)], base: usize) -> (Vec<u8>, Vec<(u32, u32)>) {
+        let mut strings = Vec::new();
+        let mut offsets = Vec::new();
+        for (_, key, path) in entries {
+            let key_offset = (base + strings.len()) as u32;
+            strings.extend_from_slice(key.as_bytes());
+            strings.push(0);
+            let path_offset = (base + strings.len()) as u32;
+            strings.extend_from_slice(path.as_bytes());
+            strings.push(0);
+            offsets.push((key_offset, path_offset));
+        }
+        (strings, offsets)
+    }
+
+    /// Old format cache, whose string offsets are relative to the end of the
+    /// entries.
+    fn old_cache(entries: &[(i32, &str, &str)]) -> Vec<u8> {
+        let mut data = OLD_MAGIC.to_vec();
+        data.push(0);
+        push_u32(&mut data, entries.len() as u32);
+        let (strings, offsets) = strings(entries, 0);
+        for ((flags, _, _), (key, path)) in entries.iter().zip(offsets) {
+            push_u32(&mut data, *flags as u32);
+            push_u32(&mut data, key);
+            push_u32(&mut data, path);
+        }
+        data.extend_from_slice(&strings);
+        data
+    }
+
+    /// New format cache, whose string offsets are relative to its header.
+    fn new_cache(entries: &[(i32, &str, &str)], endianness: u8) -> Vec<u8> {
+        let mut data = NEW_MAGIC.to_vec();
+        push_u32(&mut data, entries.len() as u32);
+        let (strings, offsets) = strings(entries, NEW_HEADER_SIZE + entries.len() * NEW_ENTRY_SIZE);
+        push_u32(&mut data, strings.len() as u32);
+        data.push(endianness);
+        data.resize(NEW_HEADER_SIZE, 0);
+        for ((flags, _, _), (key, path)) in entries.iter().zip(offsets) {
+            push_u32(&mut data, *flags as u32);
+            push_u32(&mut data, key);
+            push_u32(&mut data, path);
+            // osversion and hwcap
+            data.resize(data.len() + 12, 0);
+        }
+        data.extend_from_slice(&strings);
+        data
+    }
+
+    fn native_endianness() -> u8 {
+        if cfg!(target_endian = "little") {
+            ENDIAN_LITTLE
+        } else {
+            ENDIAN_BIG
+        }
+    }
+
+    fn entry(flags: i32, key: &str, path: &str) -> LdCacheEntry {
+        LdCacheEntry {
+            flags,
+            key: key.into(),
+            path: path.into(),
+        }
+    }
+
+    #[test]
+    fn parse_old_format() {
+        let data = old_cache(&[
+            (NATIVE, "libfoo.so.1", "/usr/lib/libfoo.so.1"),
+            (NATIVE, "libbar.so.2", "/lib/libbar.so.2"),
+        ]);
+        let cache = LdCache::parse(&data).unwrap();
+        assert_eq!(
+            cache.entries,
+            vec![
+                entry(NATIVE, "libfoo.so.1", "/usr/lib/libfoo.so.1"),
+                entry(NATIVE, "libbar.so.2", "/lib/libbar.so.2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_new_format() {
+        for endianness in [0, native_endianness()] {
+            let data = new_cache(
+                &[(NATIVE, "libfoo.so.1", "/usr/lib/libfoo.so.1")],
+                endianness,
+            );
+            let cache = LdCache::parse(&data).unwrap();
+            assert_eq!(
+                cache.entries,
+                vec![entry(NATIVE, "libfoo.so.1", "/usr/lib/libfoo.so.1")]
+            );
+        }
+    }
+
+    #[test]
+    fn parse_combined_format() {
+        // one old entry ends the old section at 28 bytes, so the new one
+        // starts after 4 bytes of padding
+        let mut data = old_cache(&[(NATIVE, "libold.so.1", "/lib/libold.so.1")]);
+        data.truncate(OLD_HEADER_SIZE + OLD_ENTRY_SIZE);
+        assert_eq!(data.len() % NEW_ALIGN, 4);
+        data.resize(data.len() + 4, 0);
+        data.extend_from_slice(&new_cache(
+            &[(NATIVE, "libnew.so.1", "/usr/lib/libnew.so.1")],
+            native_endianness(),
+        ));
+        let cache = LdCache::parse(&data).unwrap();
+        assert_eq!(
+            cache.entries,
+            vec![entry(NATIVE, "libnew.so.1", "/usr/lib/libnew.so.1")]
+        );
+    }
+
+    #[test]
+    fn reject_foreign_endianness() {
+        let foreign = if native_endianness() == ENDIAN_LITTLE {
+            ENDIAN_BIG
+        } else {
+            ENDIAN_LITTLE
+        };
+        let data = new_cache(&[(NATIVE, "libfoo.so.1", "/usr/lib/libfoo.so.1")], foreign);
+        let e = LdCache::parse(&data).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reject_invalid_data() {
+        let entries = [(NATIVE, "libfoo.so.1", "/usr/lib/libfoo.so.1")];
+        let old = old_cache(&entries);
+        let new = new_cache(&entries, native_endianness());
+
+        let mut invalid = vec![
+            b"not a cache".to_vec(),
+            // truncated headers, entries and strings
+            old[..OLD_MAGIC.len() + 2].to_vec(),
+            old[..OLD_HEADER_SIZE + 4].to_vec(),
+            old[..old.len() - 1].to_vec(),
+            new[..NEW_MAGIC.len() + 2].to_vec(),
+            new[..NEW_HEADER_SIZE + 4].to_vec(),
+            new[..new.len() - 1].to_vec(),
+        ];
+        // string offsets past the end of the data
+        let mut data = old.clone();
+        data[OLD_HEADER_SIZE + 4..OLD_HEADER_SIZE + 8].copy_from_slice(&u32::MAX.to_ne_bytes());
+        invalid.push(data);
+        let mut data = new.clone();
+        data[NEW_HEADER_SIZE + 8..NEW_HEADER_SIZE + 12].copy_from_slice(&u32::MAX.to_ne_bytes());
+        invalid.push(data);
+        // more entries than the data holds
+        let mut data = new;
+        data[NEW_MAGIC.len()..NEW_MAGIC.len() + 4].copy_from_slice(&u32::MAX.to_ne_bytes());
+        invalid.push(data);
+
+        for data in invalid {
+            let e = LdCache::parse(&data).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn native_entries() {
+        assert!(entry(NATIVE, "libfoo.so.1", "/lib/libfoo.so.1").is_native());
+        // libc5 libraries
+        assert!(!entry(0x0001 | FLAG_ARCH_NATIVE, "libfoo.so.1", "/lib/libfoo.so.1").is_native());
+        if FLAG_ARCH.is_some() {
+            let foreign = FLAG_ELF_LIBC6 | (FLAG_ARCH_NATIVE ^ 0x0100);
+            assert!(!entry(foreign, "libfoo.so.1", "/lib/libfoo.so.1").is_native());
+        }
+
+        let cache = LdCache {
+            entries: vec![
+                entry(0x0001, "libfoo.so.1", "/lib/libc5/libfoo.so.1"),
+                entry(NATIVE, "libfoo.so.1", "/lib/libfoo.so.1"),
+            ],
+        };
+        assert_eq!(
+            cache.get("libfoo.so.1"),
+            Some(Path::new("/lib/libfoo.so.1"))
+        );
+        assert_eq!(cache.get("libbar.so.1"), None);
+    }
+}
@@ -1,21 +1,50 @@
 // SPDX-License-Identifier: GPL-3.0-only
-use anyhow::{anyhow, Result};
 use futures::{channel::mpsc::Receiver, SinkExt};
 use glib::translate::ToGlibPtr;
 use gtk4::glib::object::Cast;
+use gtk4::prelude::WidgetExt;
 use gtk4::{glib, CssProvider, Orientation};
 use libloading::{Library, Symbol};
-use log::debug;
-use notify::{Event, INotifyWatcher, RecursiveMode, Watcher};
-use regex::Regex;
-use serde::{Deserialize, Serialize};
-use std::ffi::{OsStr, OsString};
+use log::{debug, error};
+use notify::{Event, EventKind, INotifyWatcher, RecursiveMode, Watcher};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use settings::StoredSettings;
+use std::cell::RefCell;
+use std::ffi::{CStr, OsStr, OsString};
+use std::os::raw::c_char;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
 // A plugin which allows you to add extra functionality to the cosmic dock/panel.
 use std::ffi::c_void;
 use thin_trait_object::*;
 
+mod error;
+pub mod ffi;
+mod host_event;
+pub mod ipc;
+mod isolated;
+mod layout;
+mod ld_cache;
+mod lifecycle;
+mod manifest;
+mod preferences;
+mod settings;
+mod supervisor;
+
+pub use error::*;
+pub use host_event::*;
+pub use isolated::{RESTART_BACKOFF_MAX, RESTART_BACKOFF_MIN, RESTART_BACKOFF_RESET};
+pub use layout::*;
+pub use ld_cache::*;
+pub use lifecycle::PluginState;
+pub use manifest::*;
+pub use preferences::{preferences_page, FieldKind, SettingsField, SettingsSchema};
+pub use settings::{
+    settings_dir, settings_path, RawSettings, Settings, SettingsFormat, SETTINGS_DIR,
+};
+pub use supervisor::{PluginHealth, RestartPolicy, DEFAULT_MAX_RETRIES, WATCHDOG_THRESHOLD};
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub enum Size {
@@ -50,24 +79,98 @@ impl Into<Orientation> for Position {
 
 #[thin_trait_object(drop_abi = "C")]
 pub trait Plugin {
-    extern "C" fn _applet(&self) -> *mut gtk4_sys::GtkBox {
-        self.applet().to_glib_full()
+    /// # Safety
+    /// `applet` must be valid for writes.
+    unsafe extern "C" fn _applet(&self, applet: *mut *mut gtk4_sys::GtkBox) -> PluginStatus {
+        catch_panic(|| unsafe { *applet = self.applet().to_glib_full() })
+    }
+    extern "C" fn _set_size(&self, size: Size) -> PluginStatus {
+        catch_panic(|| self.set_size(size))
+    }
+    extern "C" fn _set_position(&self, position: Position) -> PluginStatus {
+        catch_panic(|| self.set_position(position))
+    }
+    /// # Safety
+    /// `css_provider` must be valid for writes.
+    unsafe extern "C" fn _css_provider(
+        &self,
+        css_provider: *mut *mut gtk4_sys::GtkCssProvider,
+    ) -> PluginStatus {
+        catch_panic(|| unsafe { *css_provider = self.css_provider().to_glib_full() })
+    }
+    /// # Safety
+    /// `error` must point to a valid sink.
+    unsafe extern "C" fn _on_plugin_load(&mut self, error: *const ByteSink) -> PluginStatus {
+        let mut result = Ok(());
+        let status = catch_panic(|| {
+            result = match gtk4::init() {
+                Ok(()) => self.on_plugin_load(),
+                Err(e) => Err(e.into()),
+            };
+        });
+        match result {
+            Err(e) if status == PluginStatus::Ok => {
+                unsafe { (*error).put(e.to_string().as_bytes()) };
+                PluginStatus::Failed
+            }
+            _ => status,
+        }
+    }
+    extern "C" fn _on_plugin_unload(&mut self) -> PluginStatus {
+        catch_panic(|| self.on_plugin_unload())
+    }
+    /// # Safety
+    /// `out` must point to a valid sink.
+    unsafe extern "C" fn _save_state(&self, out: *const ByteSink) -> PluginStatus {
+        catch_panic(|| {
+            if let Some(state) = self.save_state() {
+                unsafe { (*out).put(&state) };
+            }
+        })
+    }
+    /// # Safety
+    /// `state` must point to a valid slice.
+    unsafe extern "C" fn _restore_state(&mut self, state: *const ByteSlice) -> PluginStatus {
+        catch_panic(|| self.restore_state(unsafe { (*state).as_slice() }))
     }
-    extern "C" fn _set_size(&self, size: Size) {
-        self.set_size(size);
+    /// # Safety
+    /// `version` must be valid for writes.
+    unsafe extern "C" fn _state_version(&self, version: *mut u32) -> PluginStatus {
+        catch_panic(|| unsafe { *version = self.state_version() })
     }
-    extern "C" fn _set_position(&self, position: Position) {
-        self.set_position(position);
+    extern "C" fn _on_suspend(&mut self) -> PluginStatus {
+        catch_panic(|| self.on_suspend())
     }
-    extern "C" fn _css_provider(&self) -> *mut gtk4_sys::GtkCssProvider {
-        self.css_provider().to_glib_full()
+    extern "C" fn _on_resume(&mut self) -> PluginStatus {
+        catch_panic(|| self.on_resume())
     }
-    extern "C" fn _on_plugin_load(&mut self) {
-        gtk4::init().unwrap();
-        self.on_plugin_load();
+    /// # Safety
+    /// `settings` must point to valid settings.
+    unsafe extern "C" fn _on_settings_changed(
+        &mut self,
+        settings: *const RawSettings,
+    ) -> PluginStatus {
+        catch_panic(|| self.on_settings_changed(unsafe { Settings::from_raw(&*settings) }))
     }
-    extern "C" fn _on_plugin_unload(&mut self) {
-        self.on_plugin_unload();
+    /// # Safety
+    /// `event` must point to a valid event.
+    unsafe extern "C" fn _on_host_event(&mut self, event: *const RawHostEvent) -> PluginStatus {
+        catch_panic(|| {
+            let data = unsafe { (*event).data.as_slice() };
+            // events added after the plugin was built cannot be deserialized
+            if let Ok(event) = ron::de::from_bytes::<HostEvent>(data) {
+                self.on_host_event(&event);
+            }
+        })
+    }
+    /// # Safety
+    /// `out` must point to a valid sink.
+    unsafe extern "C" fn _settings_schema(&self, out: *const ByteSink) -> PluginStatus {
+        catch_panic(|| {
+            if let Some(schema) = self.settings_schema().and_then(|s| ron::to_string(&s).ok()) {
+                unsafe { (*out).put(schema.as_bytes()) };
+            }
+        })
     }
 
     /// Get the applet
@@ -79,149 +182,1755 @@ pub trait Plugin {
     fn set_size(&self, size: Size);
     fn set_position(&self, position: Position);
     /// A callback fired immediately after the plugin is loaded. Usually used
-    /// for initialization.
-    fn on_plugin_load(&mut self);
+    /// for initialization. Returning an error aborts loading the plugin and
+    /// reports the error to the host.
+    fn on_plugin_load(&mut self) -> PluginResult;
     /// A callback fired immediately before the plugin is unloaded. Use this if
     /// you need to do any cleanup.
     fn on_plugin_unload(&mut self);
+    /// Snapshot the state of the plugin before it is unloaded for a reload,
+    /// e.g. with [`serialize_state`].
+    fn save_state(&self) -> Option<Vec<u8>> {
+        None
+    }
+    /// Restore a snapshot taken by the previous instance of the plugin, called
+    /// after `on_plugin_load` when the plugin is reloaded.
+    fn restore_state(&mut self, _state: &[u8]) {}
+    /// Version of the state format. Snapshots are discarded instead of being
+    /// restored when the version changes across a reload.
+    fn state_version(&self) -> u32 {
+        0
+    }
+    /// A callback fired when the plugin is disabled without being unloaded.
+    /// Its applet is hidden until it is resumed; stop timers and polling here.
+    fn on_suspend(&mut self) {}
+    /// A callback fired when a disabled plugin is enabled again.
+    fn on_resume(&mut self) {}
+    /// A callback fired with the settings of the instance after
+    /// `on_plugin_load`, before the applet is requested, and again whenever
+    /// they change on disk or with [`PluginManager::set_settings`]. Read them
+    /// with [`Settings::get`].
+    fn on_settings_changed(&mut self, _settings: Settings<'_>) {}
+    /// A callback fired when the environment of the panel changes. The latest
+    /// event of each kind is also passed after `on_plugin_load`, before the
+    /// applet is requested. Events this plugin does not know are ignored.
+    fn on_host_event(&mut self, _event: &HostEvent) {}
+    /// Describe the settings of the plugin along with their current values,
+    /// from which the host generates a preferences page with
+    /// [`preferences_page`].
+    fn settings_schema(&self) -> Option<SettingsSchema> {
+        None
+    }
+}
+
+/// Result of fallible [`Plugin`] hooks.
+pub type PluginResult<T = ()> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Callback passed to plugins which copies data out of the plugin, e.g. the
+/// snapshot passed to `_save_state`, the error message of `_on_plugin_load` or
+/// the RON serialized [`SettingsSchema`] passed to `_settings_schema`.
+/// It is not called if there is no data.
+pub type ByteWriter = unsafe extern "C" fn(ctx: *mut c_void, data: *const u8, len: usize);
+
+/// A [`ByteWriter`] along with the context it must be called with.
+#[repr(C)]
+pub struct ByteSink {
+    pub write: ByteWriter,
+    pub ctx: *mut c_void,
+}
+
+impl ByteSink {
+    /// Sink which copies the data into `buf`, which must outlive the call the
+    /// sink is passed to.
+    fn collect(buf: &mut Option<Vec<u8>>) -> Self {
+        Self {
+            write: write_bytes,
+            ctx: buf as *mut Option<Vec<u8>> as *mut c_void,
+        }
+    }
+
+    /// Copy `data` out of the plugin.
+    ///
+    /// # Safety
+    /// the sink must be the one passed by the host to the current call.
+    pub unsafe fn put(&self, data: &[u8]) {
+        (self.write)(self.ctx, data.as_ptr(), data.len())
+    }
+}
+
+/// Data passed into a plugin, e.g. the snapshot passed to `_restore_state`.
+/// `data` may be null if `len` is 0.
+#[repr(C)]
+pub struct ByteSlice {
+    pub data: *const u8,
+    pub len: usize,
+}
+
+impl ByteSlice {
+    fn new(data: &[u8]) -> Self {
+        Self {
+            data: data.as_ptr(),
+            len: data.len(),
+        }
+    }
+
+    /// # Safety
+    /// `data` must point to `len` bytes which stay valid for the current call.
+    pub unsafe fn as_slice(&self) -> &[u8] {
+        if self.len == 0 {
+            &[]
+        } else {
+            std::slice::from_raw_parts(self.data, self.len)
+        }
+    }
+}
+
+/// Result of a call into a plugin. Panics must not unwind across the plugin
+/// boundary, so they are caught and reported to the host instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum PluginStatus {
+    Ok,
+    Panicked,
+    /// A fallible hook returned an error, which was passed to its `ByteSink`.
+    Failed,
+}
+
+impl PluginStatus {
+    /// Convert the status of a call which cannot fail otherwise.
+    fn into_result(self, call: &'static str) -> Result<()> {
+        match self {
+            Self::Ok => Ok(()),
+            Self::Panicked | Self::Failed => Err(PluginError::Panicked(call)),
+        }
+    }
+}
+
+/// Run plugin code, catching any panic.
+#[doc(hidden)]
+pub fn catch_panic<F: FnOnce()>(f: F) -> PluginStatus {
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+        Ok(()) => PluginStatus::Ok,
+        Err(_) => PluginStatus::Panicked,
+    }
 }
 
+/// Serialize plugin state for [`Plugin::save_state`].
+pub fn serialize_state<T: Serialize>(state: &T) -> Option<Vec<u8>> {
+    match ron::to_string(state) {
+        Ok(state) => Some(state.into_bytes()),
+        Err(e) => {
+            error!("failed to serialize plugin state: {}", e);
+            None
+        }
+    }
+}
+
+/// Deserialize plugin state in [`Plugin::restore_state`].
+pub fn deserialize_state<T: DeserializeOwned>(state: &[u8]) -> Option<T> {
+    match ron::de::from_bytes(state) {
+        Ok(state) => Some(state),
+        Err(e) => {
+            error!("failed to deserialize plugin state: {}", e);
+            None
+        }
+    }
+}
+
+/// Version of the plugin ABI. Must be bumped whenever the `Plugin` trait, and
+/// with it the layout of [`PluginVtable`], or any other type crossing the
+/// library boundary changes.
+pub const PLUGIN_ABI_VERSION: u32 = 9;
+
+/// ABI fingerprint exported by every plugin library as `_plugin_abi`.
+/// [`PluginManager`] checks it before calling the plugin constructor, so a
+/// plugin built against an incompatible version of this crate is rejected
+/// instead of being cast into a [`BoxedPlugin`] with the wrong vtable layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct PluginAbi {
+    /// Must remain the first field so the version of any plugin can be read.
+    pub version: u32,
+    pub vtable_size: u32,
+    pub vtable_align: u32,
+}
+
+impl PluginAbi {
+    pub const CURRENT: Self = Self {
+        version: PLUGIN_ABI_VERSION,
+        vtable_size: std::mem::size_of::<PluginVtable>() as u32,
+        vtable_align: std::mem::align_of::<PluginVtable>() as u32,
+    };
+}
+
+/// Metadata exported by every plugin library as `_plugin_descriptor`, which
+/// lets the host identify a plugin without instantiating it. All strings are
+/// nul-terminated UTF-8 and may be null if unset.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct PluginDescriptor {
+    pub name: *const c_char,
+    pub version: *const c_char,
+    pub authors: *const c_char,
+    pub description: *const c_char,
+    pub icon: *const c_char,
+}
+
+// the descriptor only ever points to static strings
+unsafe impl Sync for PluginDescriptor {}
+
+impl PluginDescriptor {
+    /// Copy the descriptor into an owned [`PluginMetadata`].
+    ///
+    /// # Safety
+    /// every field must be null or point to a valid nul-terminated string.
+    pub unsafe fn to_metadata(&self) -> PluginMetadata {
+        unsafe fn to_string(s: *const c_char) -> String {
+            if s.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(s).to_string_lossy().into_owned()
+            }
+        }
+        PluginMetadata {
+            name: to_string(self.name),
+            version: to_string(self.version),
+            authors: to_string(self.authors),
+            description: to_string(self.description),
+            icon: to_string(self.icon),
+        }
+    }
+}
+
+/// Information about a plugin, as declared with [`declare_plugin!`].
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct PluginMetadata {
+    pub name: String,
+    pub version: String,
+    pub authors: String,
+    pub description: String,
+    /// icon name or path
+    pub icon: String,
+}
+
+/// Declare the plugin type exported by a library, along with its metadata.
+/// When no metadata block is given, it is taken from the plugin crate's
+/// `Cargo.toml`.
+///
+/// ```ignore
+/// declare_plugin!(Clock, {
+///     name: "Clock",
+///     version: "0.1.0",
+///     authors: "Jane Doe",
+///     description: "Shows the current time",
+///     icon: "preferences-system-time",
+/// });
+/// ```
 #[macro_export]
 macro_rules! declare_plugin {
     ($plugin_type:ty) => {
+        $crate::declare_plugin!($plugin_type, {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            authors: env!("CARGO_PKG_AUTHORS"),
+            description: env!("CARGO_PKG_DESCRIPTION"),
+            icon: "",
+        });
+    };
+    ($plugin_type:ty, {
+        name: $name:expr,
+        version: $version:expr,
+        authors: $authors:expr,
+        description: $description:expr,
+        icon: $icon:expr $(,)?
+    }) => {
         use gtk4::glib::translate::ToGlibPtr;
         #[no_mangle]
+        #[allow(non_upper_case_globals)]
+        pub static _plugin_abi: $crate::PluginAbi = $crate::PluginAbi::CURRENT;
+
+        #[no_mangle]
+        #[allow(non_upper_case_globals)]
+        pub static _plugin_descriptor: $crate::PluginDescriptor = $crate::PluginDescriptor {
+            name: concat!($name, "\0").as_ptr() as *const std::os::raw::c_char,
+            version: concat!($version, "\0").as_ptr() as *const std::os::raw::c_char,
+            authors: concat!($authors, "\0").as_ptr() as *const std::os::raw::c_char,
+            description: concat!($description, "\0").as_ptr() as *const std::os::raw::c_char,
+            icon: concat!($icon, "\0").as_ptr() as *const std::os::raw::c_char,
+        };
+
+        /// Returns null if the constructor panicked.
+        #[no_mangle]
         pub extern "C" fn _plugin_create() -> *mut std::ffi::c_void {
             // make sure the constructor is the correct type.
             let constructor: fn() -> $plugin_type = <$plugin_type>::default;
 
-            let object = constructor();
-            let boxed_plugin: BoxedPlugin = BoxedPlugin::new(object);
-            boxed_plugin.into_raw() as *mut std::ffi::c_void
+            std::panic::catch_unwind(|| {
+                let object = constructor();
+                let boxed_plugin: BoxedPlugin = BoxedPlugin::new(object);
+                boxed_plugin.into_raw() as *mut std::ffi::c_void
+            })
+            .unwrap_or(std::ptr::null_mut())
         }
     };
 }
 
 pub(crate) struct PluginLibrary<'a> {
+    pub(crate) id: PluginId,
     pub(crate) name: String,
+    pub(crate) manifest: Option<PluginManifest>,
     pub(crate) lib_path: OsString,
     pub(crate) plugin: BoxedPlugin<'a>,
     pub(crate) css_provider: CssProvider,
     pub(crate) applet: gtk4::Box,
-    pub(crate) loaded_library: Library,
+    /// the plugin is only called while its state is callable
+    pub(crate) state: PluginState,
+    /// duration of a call which blocked the main loop for too long
+    pub(crate) blocked: Option<Duration>,
+    /// shared with the other instances created from the library
+    pub(crate) loaded_library: Rc<SharedLibrary>,
+}
+
+impl<'a> PluginLibrary<'a> {
+    /// Move the plugin to another lifecycle state, refusing invalid
+    /// transitions.
+    fn transition(&mut self, next: PluginState) -> Result<()> {
+        if !self.state.can_transition_to(next) {
+            error!(
+                "plugin {} cannot go from {:?} to {:?}",
+                self.name, self.state, next
+            );
+            return Err(PluginError::InvalidTransition {
+                name: self.name.clone(),
+                from: self.state,
+                to: next,
+            });
+        }
+        debug!("plugin {}: {:?} -> {:?}", self.name, self.state, next);
+        self.state = next;
+        Ok(())
+    }
+
+    /// Run the hooks of a plugin which was just created and replace its
+    /// placeholder applet and css provider. A plugin which failed to load is
+    /// faulted, so it is not unloaded.
+    unsafe fn load(
+        &mut self,
+        settings: Option<&StoredSettings>,
+        environment: &[HostEvent],
+    ) -> Result<()> {
+        self.callable()?;
+        let mut message: Option<Vec<u8>> = None;
+        let status = self
+            .plugin
+            ._on_plugin_load(&ByteSink::collect(&mut message));
+        let message = match status {
+            PluginStatus::Ok => None,
+            PluginStatus::Panicked => Some("the plugin panicked.".into()),
+            PluginStatus::Failed => {
+                Some(String::from_utf8_lossy(&message.unwrap_or_default()).into_owned())
+            }
+        };
+        if let Some(message) = message {
+            let _ = self.transition(PluginState::Faulted);
+            return Err(PluginError::InitFailed {
+                name: self.name.clone(),
+                message,
+            });
+        }
+
+        self.call("on_settings_changed", |p| settings_changed(p, settings))?;
+        for event in environment {
+            self.call("on_host_event", |p| host_event(p, event))?;
+        }
+
+        // XXX gtk needs to be initialized before loading applet and css provider
+        let mut applet = std::ptr::null_mut();
+        self.call("applet", |p| p._applet(&mut applet))?;
+        if !applet.is_null() {
+            self.applet =
+                gtk4::glib::translate::from_glib_full::<_, gtk4::Box>(applet).unsafe_cast();
+        }
+
+        // get css provider
+        let mut css_provider = std::ptr::null_mut();
+        self.call("css_provider", |p| p._css_provider(&mut css_provider))?;
+        if !css_provider.is_null() {
+            self.css_provider = gtk4::glib::translate::from_glib_full(css_provider);
+        }
+        Ok(())
+    }
+
+    /// Fail loading the plugin if it may not be called anymore.
+    fn callable(&self) -> Result<()> {
+        if self.state.is_callable() {
+            return Ok(());
+        }
+        Err(PluginError::InitFailed {
+            name: self.name.clone(),
+            message: format!("the plugin is {:?}.", self.state),
+        })
+    }
+
+    /// Call a hook of a plugin which is being loaded, failing if the plugin
+    /// is not callable or the hook did not succeed.
+    fn call(
+        &mut self,
+        call: &'static str,
+        f: impl FnOnce(&mut BoxedPlugin<'a>) -> PluginStatus,
+    ) -> Result<()> {
+        self.callable()?;
+        let status = f(&mut self.plugin);
+        self.check(status);
+        status.into_result(call)
+    }
+
+    /// Mark the plugin as faulted if a call into it panicked, returning whether
+    /// it is still healthy.
+    fn check(&mut self, status: PluginStatus) -> bool {
+        if status == PluginStatus::Panicked && self.state != PluginState::Faulted {
+            error!("plugin {} panicked and is disabled", self.name);
+            let _ = self.transition(PluginState::Faulted);
+        }
+        self.state != PluginState::Faulted
+    }
+
+    /// Record a call which started at `started` if it blocked the main loop
+    /// for longer than the watchdog threshold.
+    fn watch(&mut self, started: Instant, threshold: Option<Duration>) {
+        let elapsed = started.elapsed();
+        if threshold.map_or(false, |t| elapsed > t) {
+            error!(
+                "plugin {} blocked the main loop for {:?}",
+                self.name, elapsed
+            );
+            self.blocked = Some(elapsed);
+        }
+    }
+
+    /// Suspend or resume the plugin, hiding its applet while it is suspended.
+    fn set_suspended(&mut self, suspended: bool, watchdog: Option<Duration>) {
+        let next = if suspended {
+            PluginState::Suspended
+        } else {
+            PluginState::Active
+        };
+        if self.state == next || self.transition(next).is_err() {
+            return;
+        }
+        self.applet.set_visible(!suspended);
+        let started = Instant::now();
+        let status = if suspended {
+            self.plugin._on_suspend()
+        } else {
+            self.plugin._on_resume()
+        };
+        self.check(status);
+        self.watch(started, watchdog);
+    }
+
+    /// Pass a change of the environment to the plugin.
+    fn host_event(&mut self, event: &HostEvent, watchdog: Option<Duration>) {
+        if !self.state.is_callable() {
+            return;
+        }
+        let started = Instant::now();
+        let status = host_event(&mut self.plugin, event);
+        self.check(status);
+        self.watch(started, watchdog);
+    }
+
+    /// Ask the plugin for its settings schema.
+    fn settings_schema(&mut self, watchdog: Option<Duration>) -> Option<SettingsSchema> {
+        let mut schema: Option<Vec<u8>> = None;
+        let started = Instant::now();
+        let status = unsafe {
+            self.plugin
+                ._settings_schema(&ByteSink::collect(&mut schema))
+        };
+        let ok = self.check(status);
+        self.watch(started, watchdog);
+        if !ok {
+            return None;
+        }
+        match ron::de::from_bytes(&schema?) {
+            Ok(schema) => Some(schema),
+            Err(e) => {
+                error!("invalid settings schema of plugin {}: {}", self.name, e);
+                None
+            }
+        }
+    }
+
+    /// Pass changed settings to the plugin.
+    fn apply_settings(&mut self, settings: Option<&StoredSettings>, watchdog: Option<Duration>) {
+        if !self.state.is_callable() {
+            return;
+        }
+        let started = Instant::now();
+        let status = settings_changed(&mut self.plugin, settings);
+        self.check(status);
+        self.watch(started, watchdog);
+    }
+}
+
+/// A plugin library opened once and shared by all instances created from it.
+/// It is closed when the last instance is dropped.
+pub(crate) struct SharedLibrary {
+    pub(crate) library: Library,
+    pub(crate) lib_path: PathBuf,
+    pub(crate) metadata: PluginMetadata,
+    /// only kept to be removed, which must happen after the library is closed
+    pub(crate) _shadow_copy: Option<ShadowCopy>,
+}
+
+/// A private copy of a plugin library which is opened instead of the installed
+/// library, removed when dropped.
+pub(crate) struct ShadowCopy(PathBuf);
+
+impl ShadowCopy {
+    fn new(lib_path: &Path, generation: u64) -> Result<Self> {
+        let dir = glib::user_runtime_dir().join(format!("cosmic-plugins-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let filename = lib_path
+            .file_name()
+            .ok_or_else(|| PluginError::NotFound(lib_path.display().to_string()))?;
+        let mut copy_name = OsString::from(format!("{}-", generation));
+        copy_name.push(filename);
+        let path = dir.join(copy_name);
+        std::fs::copy(lib_path, &path)?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for ShadowCopy {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.0) {
+            debug!("failed to remove {}: {}", self.0.display(), e);
+        }
+    }
 }
 
 /// library should only be unloaded and dropped after no more references tro its applet are being used.
 impl<'a> Drop for PluginLibrary<'a> {
     fn drop(&mut self) {
+        let faulted = self.state == PluginState::Faulted;
+        let _ = self.transition(PluginState::Unloading);
         let PluginLibrary {
+            id: _,
             name,
+            manifest,
             lib_path: filename,
             plugin,
             css_provider,
             applet,
+            state: _,
+            blocked: _,
             loaded_library,
         } = self;
-        plugin._on_plugin_unload();
+        if !faulted && plugin._on_plugin_unload() == PluginStatus::Panicked {
+            error!("plugin {} panicked while unloading", name);
+        }
         drop(applet);
         drop(name);
+        drop(manifest);
         drop(filename);
         drop(css_provider);
         drop(plugin);
-        // XXX must be dropped last
+        // XXX must be dropped last, closes the library with its last instance
         drop(loaded_library);
     }
 }
 
+/// Directory relative to the xdg data directories in which plugins are installed.
+pub const PLUGIN_DIR: &str = "cosmic/plugins";
+
+/// A plugin library found by [`PluginManager::discover`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredPlugin {
+    /// The name to load the plugin with.
+    pub name: String,
+    pub lib_path: PathBuf,
+    /// Path of the manifest installed next to the library, if any.
+    pub manifest: Option<PathBuf>,
+}
+
+/// Time to wait after the last change to a plugin library before reloading it,
+/// so a library which is still being written is not loaded.
+pub const RELOAD_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Handle of a loaded plugin instance, returned when the plugin is loaded. It
+/// stays the same when the plugin is reloaded or restarted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PluginId(u64);
+
+/// Changes to plugins reported by [`PluginManager::poll_events`].
+#[derive(Debug)]
+pub enum PluginEvent {
+    /// The library of a plugin changed and the plugin was reloaded. Its
+    /// previous applet must be replaced with `applet`, the previous instance
+    /// is unloaded on the next call to `poll_events`.
+    Reloaded {
+        id: PluginId,
+        name: String,
+        applet: gtk4::Box,
+    },
+    /// The changed library of a plugin could not be loaded, the previous
+    /// instance of the plugin is kept.
+    ReloadFailed {
+        id: PluginId,
+        name: String,
+        error: PluginError,
+    },
+    /// The plugin panicked and was removed. Its applet must be removed, the
+    /// plugin is unloaded on the next call to `poll_events`. Depending on its
+    /// [`RestartPolicy`] it is restarted later.
+    Faulted {
+        id: PluginId,
+        name: String,
+        applet: gtk4::Box,
+    },
+    /// A plugin which failed was loaded again, `applet` must be added.
+    Restarted {
+        id: PluginId,
+        name: String,
+        applet: gtk4::Box,
+    },
+    /// The health of a plugin changed.
+    HealthChanged {
+        id: PluginId,
+        name: String,
+        health: PluginHealth,
+    },
+    /// The plugin was moved with [`PluginManager::move_plugin`]. Its applet
+    /// must be moved to position `index` of the `alignment` group.
+    Moved {
+        id: PluginId,
+        name: String,
+        alignment: Alignment,
+        index: usize,
+    },
+}
+
 #[derive(Default)]
 pub struct PluginManager<'a> {
     libraries: Vec<PluginLibrary<'a>>,
     watcher: Option<INotifyWatcher>,
+    watcher_rx: Option<Receiver<notify::Result<Event>>>,
     watching: Vec<(String, PathBuf)>,
+    /// changed libraries with the time of their last change
+    pending_reloads: Vec<(PathBuf, Instant)>,
+    /// instances whose settings file changed with the time of its last change
+    pending_settings: Vec<(String, Instant)>,
+    settings_watched: bool,
+    /// replaced or removed instances whose applet may still be shown by the
+    /// host
+    retired: Vec<PluginLibrary<'a>>,
+    /// events which have not been returned by `poll_events` yet
+    events: Vec<PluginEvent>,
+    /// newest open library for each path, reused by new instances
+    open_libraries: Vec<Weak<SharedLibrary>>,
+    /// open private copies of libraries instead of the installed files
+    shadow_copies: bool,
+    shadow_generation: u64,
+    /// plugins running in a host process
+    isolated: Vec<Rc<RefCell<isolated::IsolatedPlugin>>>,
+    host_binary: Option<PathBuf>,
+    /// plugins loaded in process, also after they failed
+    supervised: Vec<supervisor::Supervision>,
+    default_restart_policy: RestartPolicy,
+    watchdog: Option<Duration>,
+    next_id: u64,
+    /// loaded instances in order, with their alignment group
+    layout: Vec<(PluginId, Alignment)>,
+    /// layout profile applied last
+    profile: Option<String>,
+    /// latest host event of each kind, passed to plugins when they are loaded
+    environment: Vec<HostEvent>,
+    position: Position,
+    size: Option<Size>,
 }
 
 impl<'a> PluginManager<'a> {
-    pub fn new() -> (PluginManager<'a>, Option<Receiver<notify::Result<Event>>>) {
+    pub fn new() -> PluginManager<'a> {
+        let mut manager = PluginManager {
+            watchdog: Some(WATCHDOG_THRESHOLD),
+            ..Default::default()
+        };
         // setup library watcher
         match async_watcher() {
-            Ok((watcher, rx)) => (
-                PluginManager {
-                    libraries: Vec::new(),
-                    watcher: Some(watcher),
-                    ..Default::default()
-                },
-                Some(rx),
-            ),
+            Ok((watcher, rx)) => {
+                manager.watcher = Some(watcher);
+                manager.watcher_rx = Some(rx);
+            }
+            Err(e) => error!("failed to watch plugin libraries: {}", e),
+        }
+        manager
+    }
+
+    /// Open a private copy of each plugin library instead of the installed
+    /// file, so the installed file can be overwritten while it is loaded.
+    /// Copies are removed on unload. Reloads always open a copy. Disabled by
+    /// default; only affects plugins loaded afterwards.
+    pub fn set_shadow_copies(&mut self, enabled: bool) {
+        self.shadow_copies = enabled;
+    }
+
+    /// Use a different binary to host plugins loaded with
+    /// [`PluginManager::load_plugin_isolated`] than [`ipc::HOST_BINARY`] from `PATH`.
+    pub fn set_host_binary<P: Into<PathBuf>>(&mut self, path: P) {
+        self.host_binary = Some(path.into());
+    }
+
+    fn next_id(&mut self) -> PluginId {
+        self.next_id += 1;
+        PluginId(self.next_id)
+    }
+
+    fn library(&self, id: PluginId) -> Option<&PluginLibrary<'a>> {
+        self.libraries.iter().find(|l| l.id == id)
+    }
+
+    fn find_isolated(&self, id: PluginId) -> Option<&Rc<RefCell<isolated::IsolatedPlugin>>> {
+        self.isolated.iter().find(|p| p.borrow().id == id)
+    }
+
+    fn supervision_mut(&mut self, id: PluginId) -> Result<&mut supervisor::Supervision> {
+        self.supervised
+            .iter_mut()
+            .find(|s| s.id == id)
+            .ok_or(PluginError::UnknownPlugin(id))
+    }
+
+    /// Set what happens to a plugin after it fails, for plugins loaded in
+    /// process as well as isolated plugins.
+    pub fn set_restart_policy(&mut self, id: PluginId, policy: RestartPolicy) -> Result<()> {
+        if let Some(p) = self.find_isolated(id) {
+            p.borrow_mut().policy = policy;
+            return Ok(());
+        }
+        self.supervision_mut(id)?.policy = policy;
+        Ok(())
+    }
+
+    /// Set the restart policy of plugins loaded afterwards.
+    pub fn set_default_restart_policy(&mut self, policy: RestartPolicy) {
+        self.default_restart_policy = policy;
+    }
+
+    /// Set how long a call into a plugin may block the main loop before the
+    /// plugin is degraded, or disable the watchdog with `None`. Isolated
+    /// plugins loaded before keep their previous threshold.
+    ///
+    /// For plugins loaded in process, only the hooks called by the manager
+    /// are timed, once they returned. Their own GTK callbacks, e.g. timeouts
+    /// and signal handlers, run in the main loop of the dock and cannot be
+    /// told apart from the callbacks of the dock or of other plugins, so a
+    /// plugin which blocks in them is not detected. Such plugins should be
+    /// loaded with [`PluginManager::load_plugin_isolated`], whose host is
+    /// restarted if its main loop is blocked this long for any reason.
+    pub fn set_watchdog_threshold(&mut self, threshold: Option<Duration>) {
+        self.watchdog = threshold;
+    }
+
+    /// Get the health of a plugin loaded in process or isolated.
+    pub fn health(&self, id: PluginId) -> Option<PluginHealth> {
+        self.supervised
+            .iter()
+            .find(|s| s.id == id)
+            .map(|s| s.health)
+            .or_else(|| self.find_isolated(id).map(|p| p.borrow().health))
+    }
+
+    /// Restart a plugin which failed, resetting its failure count. The new
+    /// applet is reported with [`PluginEvent::Restarted`] by the next call to
+    /// `poll_events`; the proxy applet of an isolated plugin stays the same.
+    /// A degraded plugin which is still running is only marked as running
+    /// again.
+    pub unsafe fn reenable_plugin(&mut self, id: PluginId) -> Result<()> {
+        if let Some(p) = self.find_isolated(id) {
+            return isolated::IsolatedPlugin::reenable(p);
+        }
+        let s = self.supervision_mut(id)?;
+        match s.health {
+            PluginHealth::Running => Ok(()),
+            PluginHealth::Degraded => {
+                s.failures = 0;
+                self.set_health(id, PluginHealth::Running);
+                Ok(())
+            }
+            PluginHealth::Faulted | PluginHealth::Disabled => {
+                s.failures = 0;
+                self.restart(id, PluginHealth::Running)
+            }
+        }
+    }
+
+    fn set_health(&mut self, id: PluginId, health: PluginHealth) {
+        if let Some(s) = self.supervised.iter_mut().find(|s| s.id == id) {
+            if s.health != health {
+                s.health = health;
+                self.events.push(PluginEvent::HealthChanged {
+                    id,
+                    name: s.name.clone(),
+                    health,
+                });
+            }
+        }
+    }
+
+    /// Load a new instance of a plugin which failed.
+    unsafe fn restart(&mut self, id: PluginId, health: PluginHealth) -> Result<()> {
+        let s = self
+            .supervised
+            .iter()
+            .find(|s| s.id == id)
+            .ok_or(PluginError::UnknownPlugin(id))?;
+        let (name, lib_path, manifest) = (s.name.clone(), s.lib_path.clone(), s.manifest.clone());
+        debug!("Restarting plugin {}", name);
+        let library = self.shared_library(&lib_path);
+        match library
+            .and_then(|library| self.load_library(id, name.clone(), library, manifest, None))
+        {
+            Ok(()) => {
+                let applet = self.library(id).unwrap().applet.clone();
+                self.events
+                    .push(PluginEvent::Restarted { id, name, applet });
+                self.set_health(id, health);
+                Ok(())
+            }
             Err(e) => {
-                eprintln!("{}", e);
-                (Default::default(), None)
+                error!("failed to restart plugin {}: {}", name, e);
+                self.set_health(id, PluginHealth::Disabled);
+                Err(e)
             }
         }
     }
 
-    /// library should only be unloaded and dropped after no more references to its applet are being used.
-    pub unsafe fn unload_plugin<P: AsRef<OsStr>>(&mut self, lib_path: P) {
-        if let Some(i) = self.libraries.iter().enumerate().find_map(|(i, p)| {
-            if p.lib_path == lib_path.as_ref() {
-                Some(i)
+    /// Reload the plugins whose library changed on disk and report what
+    /// happened to plugins since the last call. Should be called periodically
+    /// from the main loop, e.g. with `glib::timeout_add_local`.
+    pub unsafe fn poll_events(&mut self) -> Vec<PluginEvent> {
+        // the host has replaced the applets of reloaded, faulted and removed
+        // plugins by now
+        self.retired.clear();
+
+        let now = Instant::now();
+        if let Some(rx) = self.watcher_rx.as_mut() {
+            while let Ok(event) = rx.try_recv() {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(e) => {
+                        error!("{}", e);
+                        continue;
+                    }
+                };
+                // removing a settings file resets the settings
+                let removed = matches!(event.kind, EventKind::Remove(_));
+                if !removed && !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    continue;
+                }
+                for path in event.paths {
+                    if let Some(instance) = settings::settings_instance(&path) {
+                        match self
+                            .pending_settings
+                            .iter_mut()
+                            .find(|(i, _)| i == &instance)
+                        {
+                            Some((_, changed)) => *changed = now,
+                            None => self.pending_settings.push((instance, now)),
+                        }
+                        continue;
+                    }
+                    if removed
+                        || !self
+                            .libraries
+                            .iter()
+                            .any(|l| l.lib_path == path.as_os_str())
+                    {
+                        continue;
+                    }
+                    match self.pending_reloads.iter_mut().find(|(p, _)| p == &path) {
+                        Some((_, changed)) => *changed = now,
+                        None => self.pending_reloads.push((path, now)),
+                    }
+                }
+            }
+        }
+
+        let (ready, pending): (Vec<_>, Vec<_>) = self
+            .pending_reloads
+            .drain(..)
+            .partition(|(_, changed)| now.duration_since(*changed) >= RELOAD_DEBOUNCE);
+        self.pending_reloads = pending;
+        for (lib_path, _) in ready {
+            // all instances of the library are reloaded from the same new library
+            let mut reloaded = None;
+            for id in self.ids_by_path(&lib_path) {
+                if let Some(event) = self.reload(id, &mut reloaded) {
+                    self.events.push(event);
+                }
+            }
+        }
+        let (ready, pending): (Vec<_>, Vec<_>) = self
+            .pending_settings
+            .drain(..)
+            .partition(|(_, changed)| now.duration_since(*changed) >= RELOAD_DEBOUNCE);
+        self.pending_settings = pending;
+        for (instance, _) in ready {
+            self.reload_settings(&instance);
+        }
+        let restarts: Vec<PluginId> = self
+            .supervised
+            .iter()
+            .filter(|s| s.health == PluginHealth::Faulted)
+            .map(|s| s.id)
+            .collect();
+        for id in restarts {
+            let _ = self.restart(id, PluginHealth::Degraded);
+        }
+        self.supervise();
+        for p in &self.isolated {
+            self.events.append(&mut p.borrow_mut().events);
+        }
+        std::mem::take(&mut self.events)
+    }
+
+    /// Remove plugins which panicked, leaving them to be unloaded once the
+    /// host removed their applet, and degrade plugins which blocked the main
+    /// loop.
+    fn supervise(&mut self) {
+        let blocked: Vec<PluginId> = self
+            .libraries
+            .iter_mut()
+            .filter_map(|l| l.blocked.take().map(|_| l.id))
+            .collect();
+        for id in blocked {
+            if self.health(id) == Some(PluginHealth::Running) {
+                self.set_health(id, PluginHealth::Degraded);
+            }
+        }
+
+        let (faulted, healthy) = self
+            .libraries
+            .drain(..)
+            .partition(|l| l.state == PluginState::Faulted);
+        self.libraries = healthy;
+        for l in faulted {
+            self.events.push(PluginEvent::Faulted {
+                id: l.id,
+                name: l.name.clone(),
+                applet: l.applet.clone(),
+            });
+            if let Some(s) = self.supervised.iter_mut().find(|s| s.id == l.id) {
+                s.failures += 1;
+                let health = if s.policy.should_restart(s.failures, true) {
+                    PluginHealth::Faulted
+                } else {
+                    error!("plugin {} failed too often and is disabled", l.name);
+                    PluginHealth::Disabled
+                };
+                self.set_health(l.id, health);
+            }
+            self.retired.push(l);
+        }
+    }
+
+    /// Load a new instance of a plugin from its changed library, which is
+    /// opened into `reloaded` unless another instance opened it before. The
+    /// previous instance is only replaced once the new one is fully
+    /// constructed.
+    unsafe fn reload(
+        &mut self,
+        id: PluginId,
+        reloaded: &mut Option<Rc<SharedLibrary>>,
+    ) -> Option<PluginEvent> {
+        let i = self.libraries.iter().position(|l| l.id == id)?;
+        let name = self.libraries[i].name.clone();
+        let manifest = self.libraries[i].manifest.clone();
+        let lib_path = PathBuf::from(&self.libraries[i].lib_path);
+        let previous = self.libraries[i].state;
+        self.libraries[i].transition(PluginState::Reloading).ok()?;
+        let state = save_state(&mut self.libraries[i]);
+
+        debug!("Reloading plugin {}", name);
+        let library = match reloaded {
+            Some(library) => Ok(library.clone()),
+            // the previous library is still open, so the new one is opened from a
+            // copy, otherwise opening the same path returns the previous library.
+            None => self
+                .open_library(&lib_path, true)
+                .map(|library| reloaded.insert(library).clone()),
+        };
+        if let Err(e) =
+            library.and_then(|library| self.load_library(id, name.clone(), library, manifest, None))
+        {
+            error!("failed to reload plugin {}: {}", name, e);
+            let _ = self.libraries[i].transition(previous);
+            return Some(PluginEvent::ReloadFailed { id, name, error: e });
+        }
+
+        let mut new = self.libraries.pop().unwrap();
+        if let Some((version, state)) = state {
+            let mut new_version = 0;
+            let status = new.plugin._state_version(&mut new_version);
+            if new.check(status) && version == new_version {
+                let started = Instant::now();
+                let status = new.plugin._restore_state(&ByteSlice::new(&state));
+                new.check(status);
+                new.watch(started, self.watchdog);
             } else {
-                None
+                debug!("Discarding state of plugin {}, its version changed", name);
+            }
+        }
+        if new.state == PluginState::Faulted {
+            let _ = self.libraries[i].transition(previous);
+            return Some(PluginEvent::ReloadFailed {
+                id,
+                name,
+                error: PluginError::Panicked("restore_state"),
+            });
+        }
+        let applet = new.applet.clone();
+        let old = std::mem::replace(&mut self.libraries[i], new);
+        self.retired.push(old);
+        Some(PluginEvent::Reloaded { id, name, applet })
+    }
+
+    /// Read the settings of an instance loaded in process again, and pass them
+    /// to the plugin if they changed.
+    fn reload_settings(&mut self, instance: &str) {
+        let s = match self.supervised.iter_mut().find(|s| s.instance == instance) {
+            Some(s) => s,
+            None => return,
+        };
+        let settings = StoredSettings::current(instance, s.manifest.as_ref());
+        if settings == s.settings {
+            return;
+        }
+        debug!("Settings of {} changed", instance);
+        s.settings = settings;
+        if let Some(l) = self.libraries.iter_mut().find(|l| l.id == s.id) {
+            l.apply_settings(s.settings.as_ref(), self.watchdog);
+        }
+    }
+
+    /// Name of a plugin instance, which names its settings file. The first
+    /// instance of a plugin is named after the plugin, further instances get
+    /// a numeric suffix.
+    pub fn instance_name(&self, id: PluginId) -> Option<String> {
+        self.supervised
+            .iter()
+            .find(|s| s.id == id)
+            .map(|s| s.instance.clone())
+            .or_else(|| self.find_isolated(id).map(|p| p.borrow().instance.clone()))
+    }
+
+    fn free_instance_name(&self, name: &str) -> String {
+        let taken = |instance: &str| {
+            self.supervised.iter().any(|s| s.instance == instance)
+                || self
+                    .isolated
+                    .iter()
+                    .any(|p| p.borrow().instance == instance)
+        };
+        if !taken(name) {
+            return name.to_string();
+        }
+        (2..)
+            .map(|n| format!("{}-{}", name, n))
+            .find(|instance| !taken(instance))
+            .unwrap()
+    }
+
+    /// Get the settings of a plugin instance, the default settings if none
+    /// are stored.
+    pub fn settings<T: DeserializeOwned + Default>(&self, id: PluginId) -> Option<T> {
+        if let Some(s) = self.supervised.iter().find(|s| s.id == id) {
+            return Some(
+                s.settings
+                    .as_ref()
+                    .map_or_else(T::default, |s| s.as_settings().get()),
+            );
+        }
+        let instance = self.find_isolated(id)?.borrow().instance.clone();
+        Some(
+            StoredSettings::current(&instance, None)
+                .map_or_else(T::default, |s| s.as_settings().get()),
+        )
+    }
+
+    /// Store the settings of a plugin instance in its settings file, keeping
+    /// the format of an existing file, and pass them to the plugin. Isolated
+    /// plugins receive them once their host notices the changed file.
+    pub fn set_settings<T: Serialize>(&mut self, id: PluginId, settings: &T) -> Result<()> {
+        let instance = self
+            .instance_name(id)
+            .ok_or(PluginError::UnknownPlugin(id))?;
+        let format = StoredSettings::load(&instance)?.map_or(SettingsFormat::Ron, |s| s.format);
+        StoredSettings::save(&instance, settings, format)?;
+        self.reload_settings(&instance);
+        self.supervise();
+        Ok(())
+    }
+
+    /// Get the settings schema of a plugin loaded in process or isolated,
+    /// with the current values of its settings.
+    pub fn settings_schema(&mut self, id: PluginId) -> Option<SettingsSchema> {
+        if let Some(p) = self.find_isolated(id) {
+            return p.borrow().schema.clone();
+        }
+        let watchdog = self.watchdog;
+        let schema = self
+            .libraries
+            .iter_mut()
+            .find(|l| l.id == id && l.state.is_callable())?
+            .settings_schema(watchdog);
+        self.supervise();
+        schema
+    }
+
+    /// Build a preferences page for the settings of a plugin, see
+    /// [`preferences_page`]. `None` if the plugin has no settings schema.
+    pub fn preferences_page(&mut self, id: PluginId) -> Option<gtk4::Widget> {
+        let schema = self.settings_schema(id)?;
+        Some(preferences_page(&schema, &self.instance_name(id)?))
+    }
+
+    /// Watch the settings directory for changed settings files.
+    fn watch_settings(&mut self) {
+        if self.settings_watched {
+            return;
+        }
+        let dir = settings_dir();
+        let watched = std::fs::create_dir_all(&dir)
+            .map_err(PluginError::from)
+            .and_then(|()| Ok(self.watch_library(&dir)?));
+        match watched {
+            Ok(()) => self.settings_watched = true,
+            Err(e) => error!("failed to watch plugin settings: {}", e),
+        }
+    }
+
+    /// List the plugins installed in `$XDG_DATA_HOME/cosmic/plugins` and the
+    /// corresponding system data directories. A plugin in the user's data
+    /// directory shadows a system plugin with the same name.
+    pub fn discover() -> Vec<DiscoveredPlugin> {
+        let mut plugins: Vec<DiscoveredPlugin> = Vec::new();
+        for dir in get_paths_to_xdg_data(PLUGIN_DIR) {
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    debug!("failed to read {}: {}", dir.display(), e);
+                    continue;
+                }
+            };
+            let mut found: Vec<DiscoveredPlugin> = entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.is_file())
+                .filter_map(|lib_path| {
+                    let name = library_name(&lib_path)?;
+                    let manifest =
+                        lib_path.with_file_name(format!("{}.{}", name, MANIFEST_EXTENSION));
+                    let manifest = manifest.exists().then(|| manifest);
+                    Some(DiscoveredPlugin {
+                        name,
+                        lib_path,
+                        manifest,
+                    })
+                })
+                .filter(|d| !plugins.iter().any(|p| p.name == d.name))
+                .collect();
+            found.sort_by(|a, b| a.name.cmp(&b.name));
+            plugins.append(&mut found);
+        }
+        plugins
+    }
+
+    /// Unload a plugin loaded in process or isolated. Its applet must not be
+    /// used anymore.
+    pub unsafe fn unload_plugin(&mut self, id: PluginId) {
+        self.libraries.retain(|l| l.id != id);
+        self.supervised.retain(|s| s.id != id);
+        self.layout.retain(|(i, _)| *i != id);
+        self.isolated.retain(|p| {
+            let mut p = p.borrow_mut();
+            if p.id != id {
+                return true;
             }
-        }) {
-            self.libraries.remove(i);
+            p.shutdown();
+            false
+        });
+    }
+
+    /// Remove a plugin like [`PluginManager::unload_plugin`], but keep a
+    /// plugin loaded in process until the next call to `poll_events` so the
+    /// host can remove its applet first. Returns the applet, unless the
+    /// plugin failed and has none.
+    unsafe fn retire_plugin(&mut self, id: PluginId) -> Option<gtk4::Box> {
+        let applet = self.applet(id);
+        if let Some(i) = self.libraries.iter().position(|l| l.id == id) {
+            let library = self.libraries.remove(i);
+            self.retired.push(library);
         }
+        self.unload_plugin(id);
+        applet
     }
 
     pub unsafe fn load_plugin<P: AsRef<OsStr> + Into<String> + Clone>(
         &mut self,
         name: P,
-    ) -> Result<(&gtk4::Box, &CssProvider)> {
-        type PluginCreate<'a> = unsafe fn() -> *mut c_void;
+    ) -> Result<PluginId> {
+        self.load_instance(name.into(), None)
+    }
 
-        let lib_path = get_ld_path(name.as_ref()).ok_or(anyhow!("library could not be found."))?;
-        let lib = Library::new(&lib_path)?;
-        self.watch_library(&lib_path.parent().unwrap())?;
-        // We need to keep the library around otherwise our plugin's vtable will
-        // point to garbage.
+    unsafe fn load_instance(&mut self, name: String, instance: Option<String>) -> Result<PluginId> {
+        let lib_path = get_ld_path(&name).ok_or_else(|| PluginError::NotFound(name.clone()))?;
+        let id = self.next_id();
+        let library = self.shared_library(&lib_path)?;
+        self.load_library(id, name, library, None, instance)?;
+        Ok(id)
+    }
 
-        let constructor: Symbol<PluginCreate> = lib.get(b"_plugin_create")?;
-        let boxed_raw = constructor();
+    /// Load a plugin from the path of its library instead of its name.
+    pub unsafe fn load_plugin_from_path<P: AsRef<Path>>(
+        &mut self,
+        lib_path: P,
+    ) -> Result<PluginId> {
+        self.load_path(lib_path.as_ref(), None)
+    }
 
-        let mut plugin = BoxedPlugin::from_raw(boxed_raw as *mut ());
-        plugin._on_plugin_load();
+    /// Load a plugin from the path of its library as the instance named
+    /// `instance`, which selects its settings file.
+    pub unsafe fn load_plugin_instance_from_path<P: AsRef<Path>>(
+        &mut self,
+        lib_path: P,
+        instance: &str,
+    ) -> Result<PluginId> {
+        self.load_path(lib_path.as_ref(), Some(instance.into()))
+    }
 
-        // XXX gtk needs to be initialized before loading applet and css provider
-        let applet = plugin._applet();
-        let applet: gtk4::Box = if !applet.is_null() {
-            gtk4::glib::translate::from_glib_full::<_, gtk4::Box>(applet).unsafe_cast()
+    unsafe fn load_path(&mut self, lib_path: &Path, instance: Option<String>) -> Result<PluginId> {
+        let name = library_name(lib_path)
+            .ok_or_else(|| PluginError::NotFound(lib_path.display().to_string()))?;
+        let id = self.next_id();
+        let library = self.shared_library(lib_path)?;
+        self.load_library(id, name, library, None, instance)?;
+        Ok(id)
+    }
+
+    /// Create another instance of a plugin loaded in process from the same
+    /// library, with its own handle and applet. Instances share the static
+    /// data of the library, which is closed once the last of them is unloaded.
+    pub unsafe fn new_instance(&mut self, id: PluginId) -> Result<PluginId> {
+        let s = self
+            .supervised
+            .iter()
+            .find(|s| s.id == id)
+            .ok_or(PluginError::UnknownPlugin(id))?;
+        let (name, lib_path, manifest) = (s.name.clone(), s.lib_path.clone(), s.manifest.clone());
+        let library = self.shared_library(&lib_path)?;
+        let instance = self.next_id();
+        self.load_library(instance, name, library, manifest, None)?;
+        Ok(instance)
+    }
+
+    /// Load a plugin in a separate host process, so it cannot crash or block
+    /// the dock. Its applet is a proxy which mirrors the applet of the
+    /// plugin; only boxes, labels, buttons and images are mirrored. A host
+    /// which exits is restarted after a delay which grows with every crash.
+    /// Requires a running glib main loop.
+    pub fn load_plugin_isolated<P: AsRef<OsStr> + Into<String> + Clone>(
+        &mut self,
+        name: P,
+    ) -> Result<PluginId> {
+        self.spawn_isolated(name.into(), None)
+    }
+
+    fn spawn_isolated(&mut self, name: String, instance: Option<String>) -> Result<PluginId> {
+        let lib_path = get_ld_path(&name).ok_or_else(|| PluginError::NotFound(name.clone()))?;
+        let host_binary = self
+            .host_binary
+            .clone()
+            .unwrap_or_else(|| ipc::HOST_BINARY.into());
+        let id = self.next_id();
+        let instance = instance.unwrap_or_else(|| self.free_instance_name(&name));
+        let plugin = isolated::IsolatedPlugin::spawn(
+            id,
+            name,
+            instance,
+            lib_path,
+            host_binary,
+            self.default_restart_policy,
+            self.watchdog,
+            self.size,
+            self.position,
+            self.environment.clone(),
+        )?;
+        self.isolated.push(plugin);
+        self.layout.push((id, Alignment::default()));
+        Ok(id)
+    }
+
+    /// Load the plugin described by a manifest file, refusing it if it does
+    /// not support the current orientation or size of the panel.
+    pub unsafe fn load_plugin_from_manifest<P: AsRef<Path>>(
+        &mut self,
+        manifest_path: P,
+    ) -> Result<PluginId> {
+        let manifest = PluginManifest::from_file(manifest_path)?;
+        let orientation: Orientation = self.position.into();
+        if !manifest.supports_orientation(orientation) {
+            return Err(PluginError::UnsupportedOrientation {
+                name: manifest.name,
+                orientation,
+            });
+        }
+        if let Some(size) = self.size.filter(|&s| !manifest.supports_size(s)) {
+            return Err(PluginError::UnsupportedSize {
+                name: manifest.name,
+                size,
+            });
+        }
+        let lib_path = manifest
+            .lib_path()
+            .ok_or_else(|| PluginError::NotFound(manifest.library.clone()))?;
+        let id = self.next_id();
+        let library = self.shared_library(&lib_path)?;
+        self.load_library(id, manifest.library.clone(), library, Some(manifest), None)?;
+        Ok(id)
+    }
+
+    /// Open a plugin library, from a private copy if `shadow_copy` is set,
+    /// and make it available to new instances.
+    unsafe fn open_library(
+        &mut self,
+        lib_path: &Path,
+        shadow_copy: bool,
+    ) -> Result<Rc<SharedLibrary>> {
+        let shadow_copy = if shadow_copy {
+            self.shadow_generation += 1;
+            Some(ShadowCopy::new(lib_path, self.shadow_generation)?)
         } else {
-            gtk4::Box::new(Orientation::Vertical, 0)
+            None
         };
+        let library = Library::new(shadow_copy.as_ref().map_or(lib_path, |c| c.0.as_path()))
+            .map_err(PluginError::Open)?;
+        check_abi(&library)?;
+        let metadata = read_metadata(&library)?;
+        self.watch_library(&lib_path.parent().unwrap())?;
+        let library = Rc::new(SharedLibrary {
+            library,
+            lib_path: lib_path.to_path_buf(),
+            metadata,
+            _shadow_copy: shadow_copy,
+        });
+        self.open_libraries.retain(|l| l.strong_count() > 0);
+        self.open_libraries.push(Rc::downgrade(&library));
+        Ok(library)
+    }
 
-        // get css provider
-        let css_provider = plugin._css_provider();
-        let css_provider: CssProvider = if !css_provider.is_null() {
-            gtk4::glib::translate::from_glib_full(css_provider)
-        } else {
-            CssProvider::new()
+    /// Get the newest open library for a path, opening it if no instance of it
+    /// is loaded.
+    unsafe fn shared_library(&mut self, lib_path: &Path) -> Result<Rc<SharedLibrary>> {
+        let open = self
+            .open_libraries
+            .iter()
+            .rev()
+            .filter_map(|l| l.upgrade())
+            .find(|l| l.lib_path == lib_path);
+        match open {
+            Some(library) => Ok(library),
+            None => self.open_library(lib_path, self.shadow_copies),
+        }
+    }
+
+    unsafe fn load_library(
+        &mut self,
+        id: PluginId,
+        name: String,
+        library: Rc<SharedLibrary>,
+        manifest: Option<PluginManifest>,
+        instance: Option<String>,
+    ) -> Result<()> {
+        type PluginCreate<'a> = unsafe extern "C" fn() -> *mut c_void;
+
+        // We need to keep the library around otherwise our plugin's vtable will
+        // point to garbage.
+        let lib_path = library.lib_path.clone();
+        let constructor: Symbol<PluginCreate> = library
+            .library
+            .get(b"_plugin_create")
+            .map_err(|_| PluginError::MissingEntryPoint("_plugin_create"))?;
+        let boxed_raw = constructor();
+        if boxed_raw.is_null() {
+            return Err(PluginError::Panicked("_plugin_create"));
+        }
+
+        let plugin = BoxedPlugin::from_raw(boxed_raw as *mut ());
+
+        // reloaded and restarted plugins keep their instance name
+        let instance = match self.supervised.iter().find(|s| s.id == id) {
+            Some(s) => s.instance.clone(),
+            None => instance.unwrap_or_else(|| self.free_instance_name(&name)),
         };
+        let settings = StoredSettings::current(&instance, manifest.as_ref());
 
+        // the plugin is only called while its record is callable, the
+        // placeholder applet and css provider are replaced once it is loaded
         self.libraries.push(PluginLibrary {
-            name: name.clone().into(),
+            id,
+            name: name.clone(),
+            manifest: manifest.clone(),
             lib_path: lib_path.clone().into(),
             plugin,
-            css_provider,
-            applet,
-            loaded_library: lib,
+            css_provider: CssProvider::new(),
+            applet: gtk4::Box::new(Orientation::Vertical, 0),
+            state: PluginState::Loading,
+            blocked: None,
+            loaded_library: library,
         });
-        self.watching.push((name.into(), lib_path));
-        let PluginLibrary {
-            applet,
-            css_provider,
-            ..
-        } = self.libraries.last().unwrap();
+        let loading = self.libraries.last_mut().unwrap();
+        // dropping a plugin which failed after it was loaded unloads it again,
+        // so it can remove the timers and signal handlers it registered before
+        // its library is closed
+        if let Err(e) = loading.load(settings.as_ref(), &self.environment) {
+            self.libraries.pop();
+            return Err(e);
+        }
+
+        let suspended = self
+            .supervised
+            .iter()
+            .find(|s| s.id == id)
+            .map_or(false, |s| s.suspended);
+        match self.supervised.iter_mut().find(|s| s.id == id) {
+            Some(s) => {
+                s.lib_path = lib_path.clone();
+                s.manifest = manifest.clone();
+                s.settings = settings;
+            }
+            None => self.supervised.push(supervisor::Supervision {
+                id,
+                name: name.clone(),
+                instance,
+                settings,
+                lib_path: lib_path.clone(),
+                manifest: manifest.clone(),
+                failures: 0,
+                health: PluginHealth::Running,
+                suspended: false,
+                policy: self.default_restart_policy,
+            }),
+        }
+        if !self.layout.iter().any(|(i, _)| *i == id) {
+            self.layout.push((id, Alignment::default()));
+        }
+        let watchdog = self.watchdog;
+        let library = self.libraries.last_mut().unwrap();
+        library.transition(PluginState::Active)?;
+        if suspended {
+            library.set_suspended(true, watchdog);
+        }
+        if !self.watching.iter().any(|(_, p)| p == &lib_path) {
+            self.watching.push((name, lib_path));
+        }
+        self.watch_settings();
+        Ok(())
+    }
+
+    /// Get the restart policy of a plugin loaded in process or isolated.
+    pub fn restart_policy(&self, id: PluginId) -> Option<RestartPolicy> {
+        self.supervised
+            .iter()
+            .find(|s| s.id == id)
+            .map(|s| s.policy)
+            .or_else(|| self.find_isolated(id).map(|p| p.borrow().policy))
+    }
+
+    /// Handles of the instances in an alignment group, in order. Applets
+    /// must be arranged accordingly after the layout changed.
+    pub fn group(&self, alignment: Alignment) -> Vec<PluginId> {
+        self.layout
+            .iter()
+            .filter(|(_, a)| *a == alignment)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Get the alignment group of a plugin instance and its position in the
+    /// group.
+    pub fn slot(&self, id: PluginId) -> Option<(Alignment, usize)> {
+        let &(_, alignment) = self.layout.iter().find(|(i, _)| *i == id)?;
+        let index = self.group(alignment).iter().position(|&i| i == id)?;
+        Some((alignment, index))
+    }
+
+    /// Move a plugin instance to position `index` of an alignment group, or to
+    /// its end if the group is shorter. The manager keeps the order of the
+    /// applets; the host moves the applet when it receives
+    /// [`PluginEvent::Moved`] from the next call to `poll_events`, which is
+    /// not sent if the instance already was in that slot.
+    pub fn move_plugin(&mut self, id: PluginId, alignment: Alignment, index: usize) -> Result<()> {
+        let name = self.name(id).ok_or(PluginError::UnknownPlugin(id))?;
+        if let Some(index) = layout::move_slot(&mut self.layout, id, alignment, index)? {
+            self.events.push(PluginEvent::Moved {
+                id,
+                name,
+                alignment,
+                index,
+            });
+        }
+        Ok(())
+    }
+
+    /// Describe the arrangement of the loaded plugin instances, e.g. to store
+    /// it with [`Layout::save`] and restore it after a restart with
+    /// [`PluginManager::apply_layout`]. Plugins keep their place when they are
+    /// reloaded or restarted.
+    pub fn current_layout(&self) -> Layout {
+        let entries = self
+            .layout
+            .iter()
+            .filter_map(|&(id, alignment)| {
+                let policy = self.restart_policy(id)?;
+                Some(LayoutEntry {
+                    plugin: self.name(id)?,
+                    instance: self.instance_name(id)?,
+                    alignment,
+                    overrides: InstanceOverrides {
+                        isolated: self.find_isolated(id).is_some(),
+                        enabled: self.is_enabled(id)?,
+                        restart_policy: (policy != self.default_restart_policy).then(|| policy),
+                    },
+                })
+            })
+            .collect();
+        Layout { entries }
+    }
+
+    /// Arrange plugin instances as described by `layout`. Loaded instances
+    /// which are part of the layout are kept, other instances are removed
+    /// and missing ones are loaded; the returned changes tell the host which
+    /// applets to add and remove. Removed instances are unloaded on the next
+    /// call to `poll_events`. Instances which could not be loaded are
+    /// skipped, the rest of the layout is applied anyway. Applets must be
+    /// arranged again afterwards, see [`PluginManager::group`].
+    pub unsafe fn apply_layout(&mut self, layout: &Layout) -> LayoutChanges {
+        let mut changes = LayoutChanges::default();
+        let mut arranged: Vec<(PluginId, Alignment)> = Vec::new();
+        // instances are matched before anything is unloaded, so loading a
+        // missing instance never takes the name of one which is kept
+        let matched: Vec<Option<PluginId>> = layout
+            .entries
+            .iter()
+            .map(|entry| {
+                self.ids()
+                    .into_iter()
+                    .find(|&id| self.matches_entry(id, entry))
+            })
+            .collect();
+        for id in self.ids() {
+            if !matched.contains(&Some(id)) {
+                if let Some(applet) = self.retire_plugin(id) {
+                    changes.unloaded.push((id, applet));
+                }
+            }
+        }
+        for (entry, matched) in layout.entries.iter().zip(matched) {
+            let loaded = matched.filter(|id| !arranged.iter().any(|(i, _)| i == id));
+            let id = match loaded {
+                Some(id) => id,
+                None => match self.load_entry(entry) {
+                    Ok(id) => {
+                        changes.loaded.push(id);
+                        id
+                    }
+                    Err(e) => {
+                        error!("failed to load plugin instance {}: {}", entry.instance, e);
+                        changes.failed.push((entry.instance.clone(), e));
+                        continue;
+                    }
+                },
+            };
+            let policy = entry
+                .overrides
+                .restart_policy
+                .unwrap_or(self.default_restart_policy);
+            let _ = self.set_restart_policy(id, policy);
+            let _ = self.set_enabled(id, entry.overrides.enabled);
+            arranged.push((id, entry.alignment));
+        }
+        self.layout = arranged;
+        changes
+    }
+
+    /// Name of the layout profile applied last with
+    /// [`PluginManager::switch_profile`].
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Store the current layout as the profile `name`, which becomes the
+    /// active profile. Profiles are only written by this method, switching
+    /// profiles does not store the current layout.
+    pub fn save_profile(&mut self, name: &str) -> Result<()> {
+        self.current_layout().save(layout_path(name))?;
+        self.profile = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Switch to the layout profile `name` from the config directory, see
+    /// [`profiles`]. Only the difference between the profiles is applied, as
+    /// with [`PluginManager::apply_layout`]: instances which are part of both
+    /// keep running. The active profile is left as it was stored, changes
+    /// made since it was applied are lost unless they are stored with
+    /// [`PluginManager::save_profile`] first.
+    pub unsafe fn switch_profile(&mut self, name: &str) -> Result<LayoutChanges> {
+        let layout = Layout::from_file(layout_path(name))?;
+        let changes = self.apply_layout(&layout);
+        self.profile = Some(name.to_string());
+        Ok(changes)
+    }
+
+    /// Whether a loaded instance is the one described by a layout entry.
+    fn matches_entry(&self, id: PluginId, entry: &LayoutEntry) -> bool {
+        self.name(id).as_ref() == Some(&entry.plugin)
+            && self.instance_name(id).as_ref() == Some(&entry.instance)
+            && self.find_isolated(id).is_some() == entry.overrides.isolated
+    }
+
+    unsafe fn load_entry(&mut self, entry: &LayoutEntry) -> Result<PluginId> {
+        let instance = Some(entry.instance.clone());
+        if entry.overrides.isolated {
+            self.spawn_isolated(entry.plugin.clone(), instance)
+        } else {
+            self.load_instance(entry.plugin.clone(), instance)
+        }
+    }
+
+    /// Enable or disable a loaded plugin without unloading its library. The
+    /// applet of a disabled plugin is hidden and its `on_suspend` hook is
+    /// called, enabling it again calls `on_resume`. Plugins stay disabled
+    /// across reloads and restarts.
+    pub fn set_enabled(&mut self, id: PluginId, enabled: bool) -> Result<()> {
+        if let Some(p) = self.find_isolated(id) {
+            p.borrow_mut().set_enabled(enabled);
+            return Ok(());
+        }
+        self.supervision_mut(id)?.suspended = !enabled;
+        let watchdog = self.watchdog;
+        if let Some(l) = self.libraries.iter_mut().find(|l| l.id == id) {
+            l.set_suspended(!enabled, watchdog);
+        }
+        self.supervise();
+        Ok(())
+    }
+
+    /// Whether a loaded plugin is enabled, see [`PluginManager::set_enabled`].
+    pub fn is_enabled(&self, id: PluginId) -> Option<bool> {
+        self.supervised
+            .iter()
+            .find(|s| s.id == id)
+            .map(|s| !s.suspended)
+            .or_else(|| self.find_isolated(id).map(|p| !p.borrow().suspended))
+    }
+
+    /// Get the lifecycle state of a plugin loaded in process or isolated.
+    /// Plugins which failed stay `Faulted` until they are restarted, or
+    /// `Disabled` if they are not restarted again.
+    pub fn state(&self, id: PluginId) -> Option<PluginState> {
+        if let Some(l) = self.library(id) {
+            return Some(l.state);
+        }
+        if let Some(p) = self.find_isolated(id) {
+            return Some(p.borrow().state());
+        }
+        self.supervised
+            .iter()
+            .find(|s| s.id == id)
+            .map(|s| match s.health {
+                PluginHealth::Disabled => PluginState::Disabled,
+                _ => PluginState::Faulted,
+            })
+    }
+
+    /// Get the lifecycle state of the first loaded instance of a plugin by
+    /// name. Installed plugins which are not loaded are `Discovered`.
+    pub fn state_by_name<P: AsRef<OsStr>>(&self, name: P) -> Option<PluginState> {
+        match self.ids_by_name(&name).first() {
+            Some(&id) => self.state(id),
+            None => Self::discover()
+                .iter()
+                .any(|p| p.name.as_str() == name.as_ref())
+                .then(|| PluginState::Discovered),
+        }
+    }
+
+    /// Handles of all loaded plugins, including plugins which failed.
+    pub fn ids(&self) -> Vec<PluginId> {
+        self.supervised
+            .iter()
+            .map(|s| s.id)
+            .chain(self.isolated.iter().map(|p| p.borrow().id))
+            .collect()
+    }
 
-        Ok((applet, css_provider))
+    /// Handles of the loaded instances of a plugin by name.
+    pub fn ids_by_name<P: AsRef<OsStr>>(&self, name: P) -> Vec<PluginId> {
+        self.supervised
+            .iter()
+            .filter(|s| s.name.as_str() == name.as_ref())
+            .map(|s| s.id)
+            .chain(
+                self.isolated
+                    .iter()
+                    .map(|p| p.borrow())
+                    .filter(|p| p.name.as_str() == name.as_ref())
+                    .map(|p| p.id),
+            )
+            .collect()
+    }
+
+    /// Handles of the loaded instances of a plugin library by its path.
+    pub fn ids_by_path<P: AsRef<Path>>(&self, lib_path: P) -> Vec<PluginId> {
+        self.supervised
+            .iter()
+            .filter(|s| s.lib_path == lib_path.as_ref())
+            .map(|s| s.id)
+            .chain(
+                self.isolated
+                    .iter()
+                    .map(|p| p.borrow())
+                    .filter(|p| p.lib_path == lib_path.as_ref())
+                    .map(|p| p.id),
+            )
+            .collect()
+    }
+
+    pub fn name(&self, id: PluginId) -> Option<String> {
+        self.supervised
+            .iter()
+            .find(|s| s.id == id)
+            .map(|s| s.name.clone())
+            .or_else(|| self.find_isolated(id).map(|p| p.borrow().name.clone()))
+    }
+
+    /// Get the applet of a plugin, the proxy applet for isolated plugins.
+    pub fn applet(&self, id: PluginId) -> Option<gtk4::Box> {
+        self.library(id)
+            .map(|l| l.applet.clone())
+            .or_else(|| self.find_isolated(id).map(|p| p.borrow().applet.clone()))
+    }
+
+    pub fn css_provider(&self, id: PluginId) -> Option<CssProvider> {
+        self.library(id)
+            .map(|l| l.css_provider.clone())
+            .or_else(|| {
+                self.find_isolated(id)
+                    .map(|p| p.borrow().css_provider.clone())
+            })
     }
 
     /// Unload all plugins and loaded plugin libraries, making sure to fire
@@ -229,34 +1938,104 @@ impl<'a> PluginManager<'a> {
     /// library should only be unloaded and dropped after no more references to its applet are being used.
     pub fn unload_all(&mut self) {
         debug!("Unloading plugins");
-        for p in self.libraries.drain(..) {
+        for p in self.libraries.drain(..).chain(self.retired.drain(..)) {
             drop(p);
         }
+        for p in self.isolated.drain(..) {
+            p.borrow_mut().shutdown();
+        }
+        self.supervised.clear();
+        self.layout.clear();
         if let Some(watcher) = self.watcher.as_mut() {
             for (_, f) in self.watching.drain(..) {
                 let _ = watcher.unwatch(f.as_ref());
             }
+            if std::mem::take(&mut self.settings_watched) {
+                let _ = watcher.unwatch(&settings_dir());
+            }
         }
     }
 
+    /// Get the metadata of a plugin loaded in process.
+    pub fn metadata(&self, id: PluginId) -> Option<&PluginMetadata> {
+        self.library(id).map(|l| &l.loaded_library.metadata)
+    }
+
+    /// Get the metadata of a plugin by name. Plugins which are not loaded are
+    /// opened, but not instantiated, to read it.
+    pub unsafe fn metadata_by_name<P: AsRef<OsStr>>(&self, name: P) -> Result<PluginMetadata> {
+        if let Some(l) = self
+            .libraries
+            .iter()
+            .find(|l| l.name.as_str() == name.as_ref())
+        {
+            return Ok(l.loaded_library.metadata.clone());
+        }
+        let lib_path = get_ld_path(name.as_ref())
+            .ok_or_else(|| PluginError::NotFound(name.as_ref().to_string_lossy().into_owned()))?;
+        let lib = Library::new(&lib_path).map_err(PluginError::Open)?;
+        check_abi(&lib)?;
+        read_metadata(&lib)
+    }
+
+    /// Get the manifest a loaded plugin was loaded from.
+    pub fn manifest(&self, id: PluginId) -> Option<&PluginManifest> {
+        self.library(id).and_then(|l| l.manifest.as_ref())
+    }
+
     pub fn paths(&self) -> Vec<OsString> {
         self.libraries.iter().map(|l| l.lib_path.clone()).collect()
     }
 
+    /// Applets of the plugins loaded in process, in layout order.
     pub fn applets(&self) -> Vec<&gtk4::Box> {
-        self.libraries.iter().map(|l| &l.applet).collect()
+        self.layout
+            .iter()
+            .filter_map(|&(id, _)| self.library(id))
+            .map(|l| &l.applet)
+            .collect()
+    }
+
+    pub fn set_size(&mut self, size: Size) {
+        self.size = Some(size);
+        for l in self.libraries.iter_mut().filter(|l| l.state.is_callable()) {
+            let started = Instant::now();
+            let status = l.plugin._set_size(size);
+            l.check(status);
+            l.watch(started, self.watchdog);
+        }
+        for p in &self.isolated {
+            p.borrow_mut().set_size(size);
+        }
+        self.supervise();
     }
 
-    pub fn set_size(&self, size: Size) {
-        for l in &self.libraries {
-            l.plugin._set_size(size);
+    pub fn set_position(&mut self, p: Position) {
+        self.position = p;
+        for l in self.libraries.iter_mut().filter(|l| l.state.is_callable()) {
+            let started = Instant::now();
+            let status = l.plugin._set_position(p);
+            l.check(status);
+            l.watch(started, self.watchdog);
         }
+        for i in &self.isolated {
+            i.borrow_mut().set_position(p);
+        }
+        self.supervise();
     }
 
-    pub fn set_position(&self, p: Position) {
-        for l in &self.libraries {
-            l.plugin._set_position(p);
+    /// Tell all plugins about a change to the environment of the panel.
+    /// Plugins loaded later receive the latest event of each kind when they
+    /// are loaded.
+    pub fn send_event(&mut self, event: &HostEvent) {
+        host_event::remember(&mut self.environment, event);
+        for l in self.libraries.iter_mut() {
+            l.host_event(event, self.watchdog);
+        }
+        for p in &self.isolated {
+            p.borrow_mut().send_event(event);
         }
+        self.supervise();
     }
 
     pub fn library_path_to_applet<T: AsRef<OsStr>>(&self, lib_filename: T) -> Option<&gtk4::Box> {
@@ -287,29 +2066,129 @@ impl<'a> PluginManager<'a> {
     }
 }
 
+/// Make sure the library was built against a compatible plugin ABI before any
+/// of its code is run.
+unsafe fn check_abi(lib: &Library) -> Result<()> {
+    let abi: Symbol<*const PluginAbi> = lib
+        .get(b"_plugin_abi")
+        .map_err(|_| PluginError::MissingEntryPoint("_plugin_abi"))?;
+    let abi: *const PluginAbi = *abi;
+    // only the version is guaranteed to be readable for every ABI version
+    let version = std::ptr::addr_of!((*abi).version).read();
+    if version != PLUGIN_ABI_VERSION {
+        return Err(PluginError::VersionMismatch {
+            found: version,
+            expected: PLUGIN_ABI_VERSION,
+        });
+    }
+    if *abi != PluginAbi::CURRENT {
+        return Err(PluginError::LayoutMismatch {
+            found: *abi,
+            expected: PluginAbi::CURRENT,
+        });
+    }
+    Ok(())
+}
+
+/// [`ByteWriter`] which copies the data into the `Option<Vec<u8>>` behind `ctx`.
+unsafe extern "C" fn write_bytes(ctx: *mut c_void, data: *const u8, len: usize) {
+    let buf = &mut *(ctx as *mut Option<Vec<u8>>);
+    *buf = Some(std::slice::from_raw_parts(data, len).to_vec());
+}
+
+/// Call the `on_settings_changed` hook of a plugin, with empty settings if
+/// none are stored.
+fn settings_changed(plugin: &mut BoxedPlugin, settings: Option<&StoredSettings>) -> PluginStatus {
+    let settings = settings.map_or(
+        Settings {
+            format: SettingsFormat::Ron,
+            data: &[],
+        },
+        StoredSettings::as_settings,
+    );
+    unsafe { plugin._on_settings_changed(&settings.to_raw()) }
+}
+
+/// Call the `on_host_event` hook of a plugin.
+fn host_event(plugin: &mut BoxedPlugin, event: &HostEvent) -> PluginStatus {
+    let data = ron::to_string(event).unwrap_or_default();
+    unsafe {
+        plugin._on_host_event(&RawHostEvent {
+            kind: event.kind(),
+            data: ByteSlice::new(data.as_bytes()),
+        })
+    }
+}
+
+/// Take a snapshot of the state of a plugin along with its state version.
+fn save_state(library: &mut PluginLibrary) -> Option<(u32, Vec<u8>)> {
+    if !library.state.is_callable() {
+        return None;
+    }
+    let mut state: Option<Vec<u8>> = None;
+    let status = unsafe { library.plugin._save_state(&ByteSink::collect(&mut state)) };
+    if !library.check(status) {
+        return None;
+    }
+    let mut version = 0;
+    let status = unsafe { library.plugin._state_version(&mut version) };
+    if !library.check(status) {
+        return None;
+    }
+    state.map(|state| (version, state))
+}
+
+/// Read the descriptor exported by a library which passed [`check_abi`].
+unsafe fn read_metadata(lib: &Library) -> Result<PluginMetadata> {
+    let descriptor: Symbol<*const PluginDescriptor> = lib
+        .get(b"_plugin_descriptor")
+        .map_err(|_| PluginError::MissingEntryPoint("_plugin_descriptor"))?;
+    Ok((**descriptor).to_metadata())
+}
+
 fn async_watcher() -> notify::Result<(INotifyWatcher, Receiver<notify::Result<Event>>)> {
     use futures::channel::mpsc::channel;
     let (mut tx, rx) = channel(100);
 
-    let watcher = INotifyWatcher::new(move |res| {
-        futures::executor::block_on(async {
-            tx.send(res).await.unwrap();
-        })
-    })?;
+    let watcher = INotifyWatcher::new(
+        move |res| {
+            futures::executor::block_on(async {
+                tx.send(res).await.unwrap();
+            })
+        },
+        notify::Config::default(),
+    )?;
 
     Ok((watcher, rx))
 }
 
 pub fn get_path_to_xdg_data<T: AsRef<Path>>(name: T) -> Option<PathBuf> {
+    get_paths_to_xdg_data(name).into_iter().next()
+}
+
+/// All existing paths to `name` in the xdg data directories, by priority.
+pub fn get_paths_to_xdg_data<T: AsRef<Path>>(name: T) -> Vec<PathBuf> {
     let mut data_dirs = vec![gtk4::glib::user_data_dir()];
     data_dirs.append(&mut gtk4::glib::system_data_dirs());
-    for mut p in data_dirs {
-        p.push(&name);
-        if p.exists() {
-            return Some(p);
-        }
-    }
-    None
+    data_dirs
+        .into_iter()
+        .map(|mut p| {
+            p.push(&name);
+            p
+        })
+        .filter(|p| p.exists())
+        .collect()
+}
+
+/// Get the name of a plugin from the file name of its library, which is the
+/// inverse of [`libloading::library_filename`].
+pub fn library_name<T: AsRef<Path>>(lib_path: T) -> Option<String> {
+    let filename = lib_path.as_ref().file_name()?.to_str()?;
+    filename
+        .strip_prefix(std::env::consts::DLL_PREFIX)?
+        .strip_suffix(std::env::consts::DLL_SUFFIX)
+        .filter(|name| !name.is_empty())
+        .map(String::from)
 }
 
 pub fn get_ld_path<T: AsRef<Path>>(lib_name: T) -> Option<PathBuf> {
@@ -324,23 +2203,27 @@ pub fn get_ld_path<T: AsRef<Path>>(lib_name: T) -> Option<PathBuf> {
         }
     }
 
-    // check output of ldconfig
-    if let Some(Ok(re)) = &filename
-        .to_str()
-        .map(|s| Regex::new(format!(r"\s*{}\s.*=>\s(.+)\s", s).as_str()))
-    {
-        if let Ok(Ok(cap)) = Command::new("ldconfig")
-            .arg("-p")
-            .output()
-            .map(|o| String::from_utf8(o.stdout))
-            .map(|o| {
-                re.captures_iter(&o?)
-                    .next()
-                    .map(|cap| cap[1].to_string())
-                    .ok_or(anyhow!("no match"))
-            })
-        {
-            return Some(cap.into());
+    // check plugins installed in the xdg data directories
+    for mut path in get_paths_to_xdg_data(PLUGIN_DIR) {
+        path.push(&filename);
+        if path.exists() {
+            return Some(path);
+        }
+    }
+
+    // check the dynamic linker cache, then the directories it falls back to
+    match LdCache::load() {
+        Ok(cache) => {
+            if let Some(path) = cache.get(&filename) {
+                return Some(path.to_path_buf());
+            }
+        }
+        Err(e) => debug!("failed to read {}: {}", LD_CACHE_PATH, e),
+    }
+    for dir in DEFAULT_LIB_DIRS {
+        let path = Path::new(dir).join(&filename);
+        if path.exists() {
+            return Some(path);
         }
     }
     None
@@ -0,0 +1,115 @@
+// SPDX-License-Identifier: GPL-3.0-only
+//! Lifecycle of a plugin instance.
+use serde::{Deserialize, Serialize};
+
+/// State of a plugin, reported by [`PluginManager::state`](crate::PluginManager::state).
+///
+/// Loading a `Discovered` plugin or restarting a `Faulted` one creates a new
+/// instance, which starts in `Loading`. A `Faulted` plugin which is not
+/// restarted again is reported as `Disabled`. Valid transitions of an
+/// instance:
+/// - `Loading` → `Active` or `Suspended`
+/// - `Active` ↔ `Suspended`
+/// - `Active` or `Suspended` → `Reloading` → `Active` or `Suspended`
+/// - `Loading`, `Active`, `Suspended` or `Reloading` → `Faulted`
+/// - every state but `Discovered`, `Disabled` and `Unloading` → `Unloading`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginState {
+    /// Installed, but not loaded.
+    Discovered,
+    /// The library is being opened and the plugin constructed.
+    Loading,
+    Active,
+    /// Disabled by the host, see [`PluginManager::set_enabled`](crate::PluginManager::set_enabled).
+    Suspended,
+    /// The state of the plugin is being saved for a new instance, which
+    /// replaces it once it is loaded.
+    Reloading,
+    /// The plugin panicked or its host process exited. It is not called
+    /// anymore.
+    Faulted,
+    /// The plugin failed too often and is not restarted until it is
+    /// re-enabled, see [`PluginHealth::Disabled`](crate::PluginHealth::Disabled).
+    Disabled,
+    /// The plugin is being torn down. It is not called anymore.
+    Unloading,
+}
+
+impl PluginState {
+    /// Whether a plugin may go from this state to `next`.
+    pub fn can_transition_to(self, next: Self) -> bool {
+        use PluginState::*;
+        match (self, next) {
+            (Loading | Reloading, Active | Suspended) => true,
+            (Active, Suspended) | (Suspended, Active) => true,
+            (Active | Suspended, Reloading) => true,
+            (Loading | Active | Suspended | Reloading, Faulted) => true,
+            (Discovered | Disabled | Unloading, Unloading) => false,
+            (_, Unloading) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the manager may call into a plugin in this state.
+    pub fn is_callable(self) -> bool {
+        matches!(
+            self,
+            Self::Loading | Self::Active | Self::Suspended | Self::Reloading
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PluginState::{self, *};
+
+    const STATES: [PluginState; 8] = [
+        Discovered, Loading, Active, Suspended, Reloading, Faulted, Disabled, Unloading,
+    ];
+
+    #[test]
+    fn transitions() {
+        let valid = [
+            (Loading, Active),
+            (Loading, Suspended),
+            (Loading, Faulted),
+            (Loading, Unloading),
+            (Active, Suspended),
+            (Active, Reloading),
+            (Active, Faulted),
+            (Active, Unloading),
+            (Suspended, Active),
+            (Suspended, Reloading),
+            (Suspended, Faulted),
+            (Suspended, Unloading),
+            (Reloading, Active),
+            (Reloading, Suspended),
+            (Reloading, Faulted),
+            (Reloading, Unloading),
+            (Faulted, Unloading),
+        ];
+        for from in STATES {
+            for to in STATES {
+                assert_eq!(
+                    from.can_transition_to(to),
+                    valid.contains(&(from, to)),
+                    "{:?} -> {:?}",
+                    from,
+                    to
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn callable_states() {
+        for state in STATES {
+            assert_eq!(
+                state.is_callable(),
+                matches!(state, Loading | Active | Suspended | Reloading),
+                "{:?}",
+                state
+            );
+        }
+    }
+}
@@ -0,0 +1,84 @@
+// SPDX-License-Identifier: GPL-3.0-only
+use crate::{Position, Result, Size};
+use gtk4::Orientation;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Extension of plugin manifest files, which are installed next to the plugin
+/// library as `<library name>.toml`.
+pub const MANIFEST_EXTENSION: &str = "toml";
+
+/// Describes a plugin without loading its library.
+///
+/// ```toml
+/// library = "cosmic_clock"
+/// name = "Clock"
+/// icon = "preferences-system-time"
+/// positions = ["Top", "Bottom"]
+/// sizes = ["Small", "Medium"]
+///
+/// [settings]
+/// format = "%H:%M"
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PluginManifest {
+    /// Name of the plugin library, looked up next to the manifest first.
+    pub library: String,
+    /// Display name
+    pub name: String,
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// Positions the plugin supports, all of them if omitted.
+    #[serde(default = "all_positions")]
+    pub positions: Vec<Position>,
+    /// Sizes the plugin supports, all of them if omitted.
+    #[serde(default = "all_sizes")]
+    pub sizes: Vec<Size>,
+    /// Default settings of the plugin.
+    #[serde(default)]
+    pub settings: toml::value::Table,
+    /// Path of the manifest file this was read from.
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+fn all_positions() -> Vec<Position> {
+    vec![
+        Position::Start,
+        Position::End,
+        Position::Top,
+        Position::Bottom,
+    ]
+}
+
+fn all_sizes() -> Vec<Size> {
+    vec![Size::Small, Size::Medium, Size::Large]
+}
+
+impl PluginManifest {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut manifest: Self = toml::from_str(&std::fs::read_to_string(&path)?)?;
+        manifest.path = path.as_ref().to_path_buf();
+        Ok(manifest)
+    }
+
+    /// Whether the plugin supports a panel with the given orientation.
+    pub fn supports_orientation(&self, orientation: Orientation) -> bool {
+        self.positions
+            .iter()
+            .any(|&p| Into::<Orientation>::into(p) == orientation)
+    }
+
+    pub fn supports_size(&self, size: Size) -> bool {
+        self.sizes.contains(&size)
+    }
+
+    /// Path of the plugin library, preferring one next to the manifest.
+    pub fn lib_path(&self) -> Option<PathBuf> {
+        self.path
+            .parent()
+            .map(|dir| dir.join(libloading::library_filename(&self.library)))
+            .filter(|p| p.exists())
+            .or_else(|| crate::get_ld_path(&self.library))
+    }
+}
@@ -0,0 +1,214 @@
+// SPDX-License-Identifier: GPL-3.0-only
+//! Preferences pages generated from the settings schema of a plugin.
+use crate::settings::{settings_path, StoredSettings};
+use crate::{PluginError, Result, SettingsFormat};
+use gtk4::prelude::*;
+use gtk4::{Align, Orientation};
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Description of the settings of a plugin, returned by
+/// [`Plugin::settings_schema`](crate::Plugin::settings_schema), from which
+/// [`preferences_page`] builds a preferences page.
+///
+/// The settings are stored as a struct with one field per entry of `fields`,
+/// so every field of the settings type of the plugin should be described.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SettingsSchema {
+    pub fields: Vec<SettingsField>,
+}
+
+/// A field of the settings of a plugin.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SettingsField {
+    /// Name of the field in the settings type.
+    pub key: String,
+    /// Label shown next to the field.
+    pub label: String,
+    pub kind: FieldKind,
+}
+
+/// Type and current value of a settings field, which selects its widget.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum FieldKind {
+    /// Shown as a switch.
+    Bool(bool),
+    /// Shown as a spin button.
+    Integer {
+        value: i64,
+        min: i64,
+        max: i64,
+        step: i64,
+    },
+    /// Shown as a spin button with `digits` decimal places.
+    Float {
+        value: f64,
+        min: f64,
+        max: f64,
+        step: f64,
+        digits: u32,
+    },
+    /// Shown as an entry.
+    Text(String),
+    /// A unit variant of an enum, shown as a dropdown of the names of its
+    /// variants.
+    Choice { value: String, options: Vec<String> },
+}
+
+impl FieldKind {
+    fn to_ron(&self) -> String {
+        match self {
+            Self::Bool(value) => value.to_string(),
+            Self::Integer { value, .. } => value.to_string(),
+            Self::Float { value, .. } => ron::to_string(value).unwrap_or_default(),
+            Self::Text(value) => ron::to_string(value).unwrap_or_default(),
+            Self::Choice { value, .. } => value.clone(),
+        }
+    }
+
+    fn to_toml(&self) -> toml::Value {
+        match self {
+            Self::Bool(value) => toml::Value::Boolean(*value),
+            Self::Integer { value, .. } => toml::Value::Integer(*value),
+            Self::Float { value, .. } => toml::Value::Float(*value),
+            Self::Text(value) | Self::Choice { value, .. } => toml::Value::String(value.clone()),
+        }
+    }
+}
+
+impl SettingsSchema {
+    /// Serialize the values of the fields in `format`, as the settings
+    /// struct of the plugin would be serialized.
+    fn serialize(&self, format: SettingsFormat) -> Result<String> {
+        match format {
+            SettingsFormat::Ron => {
+                let mut ron = String::from("(\n");
+                for field in &self.fields {
+                    ron.push_str(&format!("    {}: {},\n", field.key, field.kind.to_ron()));
+                }
+                ron.push_str(")\n");
+                Ok(ron)
+            }
+            SettingsFormat::Toml => {
+                let table: toml::value::Table = self
+                    .fields
+                    .iter()
+                    .map(|field| (field.key.clone(), field.kind.to_toml()))
+                    .collect();
+                toml::to_string_pretty(&table).map_err(|e| PluginError::Settings(e.to_string()))
+            }
+        }
+    }
+
+    /// Store the values of the fields as the settings of an instance,
+    /// keeping the format of an existing settings file.
+    pub fn save(&self, instance: &str) -> Result<()> {
+        let format = StoredSettings::load(instance)?.map_or(SettingsFormat::Ron, |s| s.format);
+        let data = self.serialize(format)?;
+        StoredSettings::write(&settings_path(instance, format), data.as_bytes())
+    }
+}
+
+/// Build a preferences page for the settings of a plugin instance. Every
+/// change is stored in the settings file of the instance right away, from
+/// which [`PluginManager`](crate::PluginManager) passes it to the plugin.
+pub fn preferences_page(schema: &SettingsSchema, instance: &str) -> gtk4::Widget {
+    let page = gtk4::Box::new(Orientation::Vertical, 12);
+    page.add_css_class("plugin-preferences");
+    let schema = Rc::new(RefCell::new(schema.clone()));
+    let instance: Rc<str> = instance.into();
+    let fields = schema.borrow().fields.clone();
+    for (i, field) in fields.into_iter().enumerate() {
+        let row = gtk4::Box::new(Orientation::Horizontal, 12);
+        let label = gtk4::Label::new(Some(&field.label));
+        label.set_hexpand(true);
+        label.set_xalign(0.0);
+        row.append(&label);
+
+        let schema = schema.clone();
+        let instance = instance.clone();
+        // replace the value of the field and store the settings
+        let update = move |kind: FieldKind| {
+            let mut schema = schema.borrow_mut();
+            schema.fields[i].kind = kind;
+            if let Err(e) = schema.save(&instance) {
+                error!("failed to store settings of {}: {}", instance, e);
+            }
+        };
+        let control: gtk4::Widget = match field.kind {
+            FieldKind::Bool(value) => {
+                let switch = gtk4::Switch::new();
+                switch.set_active(value);
+                switch.connect_active_notify(move |s| update(FieldKind::Bool(s.is_active())));
+                switch.upcast()
+            }
+            FieldKind::Integer {
+                value,
+                min,
+                max,
+                step,
+            } => {
+                let spin = gtk4::SpinButton::with_range(min as f64, max as f64, step as f64);
+                spin.set_value(value as f64);
+                spin.connect_value_changed(move |s| {
+                    update(FieldKind::Integer {
+                        value: s.value() as i64,
+                        min,
+                        max,
+                        step,
+                    })
+                });
+                spin.upcast()
+            }
+            FieldKind::Float {
+                value,
+                min,
+                max,
+                step,
+                digits,
+            } => {
+                let spin = gtk4::SpinButton::with_range(min, max, step);
+                spin.set_digits(digits);
+                spin.set_value(value);
+                spin.connect_value_changed(move |s| {
+                    update(FieldKind::Float {
+                        value: s.value(),
+                        min,
+                        max,
+                        step,
+                        digits,
+                    })
+                });
+                spin.upcast()
+            }
+            FieldKind::Text(value) => {
+                let entry = gtk4::Entry::new();
+                entry.set_text(&value);
+                entry.connect_changed(move |e| update(FieldKind::Text(e.text().to_string())));
+                entry.upcast()
+            }
+            FieldKind::Choice { value, options } => {
+                let strings: Vec<&str> = options.iter().map(String::as_str).collect();
+                let dropdown = gtk4::DropDown::from_strings(&strings);
+                if let Some(selected) = options.iter().position(|o| o == &value) {
+                    dropdown.set_selected(selected as u32);
+                }
+                dropdown.connect_selected_notify(move |d| {
+                    if let Some(value) = options.get(d.selected() as usize) {
+                        update(FieldKind::Choice {
+                            value: value.clone(),
+                            options: options.clone(),
+                        })
+                    }
+                });
+                dropdown.upcast()
+            }
+        };
+        control.set_valign(Align::Center);
+        row.append(&control);
+        page.append(&row);
+    }
+    page.upcast()
+}
@@ -0,0 +1,196 @@
+// SPDX-License-Identifier: GPL-3.0-only
+//! Persistent settings of plugin instances.
+use crate::{ByteSlice, PluginError, PluginManifest, Result};
+use gtk4::glib;
+use log::error;
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Directory relative to `$XDG_CONFIG_HOME` in which the settings of plugin
+/// instances are stored, as `<instance>.ron` or `<instance>.toml`.
+pub const SETTINGS_DIR: &str = "cosmic/plugins";
+
+/// Format of stored plugin settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum SettingsFormat {
+    Ron,
+    Toml,
+}
+
+impl SettingsFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Ron => "ron",
+            Self::Toml => "toml",
+        }
+    }
+
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "ron" => Some(Self::Ron),
+            "toml" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+}
+
+/// Serialized settings of a plugin instance, passed to
+/// [`Plugin::on_settings_changed`](crate::Plugin::on_settings_changed). The
+/// data is empty if no settings are stored.
+#[derive(Debug, Clone, Copy)]
+pub struct Settings<'a> {
+    pub format: SettingsFormat,
+    pub data: &'a [u8],
+}
+
+/// [`Settings`] as passed to `_on_settings_changed`.
+#[repr(C)]
+pub struct RawSettings {
+    pub format: SettingsFormat,
+    pub data: ByteSlice,
+}
+
+impl<'a> Settings<'a> {
+    pub(crate) fn to_raw(self) -> RawSettings {
+        RawSettings {
+            format: self.format,
+            data: ByteSlice::new(self.data),
+        }
+    }
+
+    /// # Safety
+    /// `raw` must be the settings passed by the host to the current call.
+    pub unsafe fn from_raw(raw: &'a RawSettings) -> Self {
+        Self {
+            format: raw.format,
+            data: raw.data.as_slice(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Deserialize the settings into the settings type of the plugin. Missing
+    /// or invalid settings are replaced by the default settings.
+    pub fn get<T: DeserializeOwned + Default>(&self) -> T {
+        if self.is_empty() {
+            return T::default();
+        }
+        match self.parse() {
+            Ok(settings) => settings,
+            Err(e) => {
+                error!("{}", e);
+                T::default()
+            }
+        }
+    }
+
+    /// Deserialize the settings, failing if they are missing or invalid.
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T> {
+        let data =
+            std::str::from_utf8(self.data).map_err(|e| PluginError::Settings(e.to_string()))?;
+        match self.format {
+            SettingsFormat::Ron => ron::from_str(data).map_err(|e| e.to_string()),
+            SettingsFormat::Toml => toml::from_str(data).map_err(|e| e.to_string()),
+        }
+        .map_err(PluginError::Settings)
+    }
+}
+
+/// Settings of a plugin instance as read from its settings file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct StoredSettings {
+    pub(crate) format: SettingsFormat,
+    pub(crate) data: Vec<u8>,
+}
+
+impl StoredSettings {
+    pub(crate) fn as_settings(&self) -> Settings<'_> {
+        Settings {
+            format: self.format,
+            data: &self.data,
+        }
+    }
+
+    /// Read the settings of an instance, preferring `.ron` over `.toml`.
+    pub(crate) fn load(instance: &str) -> Result<Option<Self>> {
+        for format in [SettingsFormat::Ron, SettingsFormat::Toml] {
+            match std::fs::read(settings_path(instance, format)) {
+                Ok(data) => return Ok(Some(Self { format, data })),
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Read the settings of an instance, falling back to the default settings
+    /// of its manifest.
+    pub(crate) fn current(instance: &str, manifest: Option<&PluginManifest>) -> Option<Self> {
+        match Self::load(instance) {
+            Ok(Some(settings)) => return Some(settings),
+            Ok(None) => {}
+            Err(e) => error!("failed to read settings of {}: {}", instance, e),
+        }
+        let defaults = &manifest?.settings;
+        if defaults.is_empty() {
+            return None;
+        }
+        let data = toml::to_string(defaults).ok()?.into_bytes();
+        Some(Self {
+            format: SettingsFormat::Toml,
+            data,
+        })
+    }
+
+    /// Serialize settings and store them for an instance.
+    pub(crate) fn save<T: Serialize>(
+        instance: &str,
+        settings: &T,
+        format: SettingsFormat,
+    ) -> Result<Self> {
+        let data = match format {
+            SettingsFormat::Ron => {
+                ron::ser::to_string_pretty(settings, Default::default()).map_err(|e| e.to_string())
+            }
+            SettingsFormat::Toml => toml::to_string_pretty(settings).map_err(|e| e.to_string()),
+        }
+        .map_err(PluginError::Settings)?
+        .into_bytes();
+        Self::write(&settings_path(instance, format), &data)?;
+        Ok(Self { format, data })
+    }
+
+    /// Replace a settings file at once, so it is never read while only
+    /// partially written.
+    pub(crate) fn write(path: &Path, data: &[u8]) -> Result<()> {
+        std::fs::create_dir_all(settings_dir())?;
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Directory in which the settings of plugin instances are stored.
+pub fn settings_dir() -> PathBuf {
+    glib::user_config_dir().join(SETTINGS_DIR)
+}
+
+/// Path of the settings file of a plugin instance in the given format.
+pub fn settings_path(instance: &str, format: SettingsFormat) -> PathBuf {
+    settings_dir().join(format!("{}.{}", instance, format.extension()))
+}
+
+/// Name of the instance whose settings are stored at `path`, if it is a
+/// settings file.
+pub(crate) fn settings_instance(path: &Path) -> Option<String> {
+    if path.parent()? != settings_dir() {
+        return None;
+    }
+    SettingsFormat::from_path(path)?;
+    Some(path.file_stem()?.to_str()?.to_string())
+}
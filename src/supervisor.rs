@@ -0,0 +1,82 @@
+// SPDX-License-Identifier: GPL-3.0-only
+//! Restart policies and health of plugins.
+use crate::settings::StoredSettings;
+use crate::{PluginId, PluginManifest};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Time a call into a plugin may block the main loop before the plugin is
+/// considered degraded. Hosts of isolated plugins which do not respond for
+/// this long are restarted. Plugins loaded in process are only watched while
+/// the manager calls them, see
+/// [`PluginManager::set_watchdog_threshold`](crate::PluginManager::set_watchdog_threshold).
+pub const WATCHDOG_THRESHOLD: Duration = Duration::from_secs(2);
+
+/// Number of restarts allowed by the default [`RestartPolicy`].
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// What happens to a plugin after it panicked or its host process exited.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Disable the plugin after its first failure.
+    Never,
+    /// Restart the plugin after a failure, until it failed more than
+    /// `max_retries` times.
+    OnFailure { max_retries: u32 },
+    /// Always restart the plugin, also when its host process exited cleanly.
+    Always,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self::OnFailure {
+            max_retries: DEFAULT_MAX_RETRIES,
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Whether a plugin which stopped after `failures` failures should be
+    /// restarted. `failed` is false if it stopped without an error.
+    pub fn should_restart(self, failures: u32, failed: bool) -> bool {
+        match self {
+            Self::Never => false,
+            Self::OnFailure { max_retries } => failed && failures <= max_retries,
+            Self::Always => true,
+        }
+    }
+}
+
+/// Health of a loaded plugin, reported by [`PluginManager::health`](crate::PluginManager::health).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginHealth {
+    /// The plugin works as expected.
+    Running,
+    /// The plugin works, but it blocked the main loop or was restarted after
+    /// a failure. It stays degraded until it is re-enabled with
+    /// [`PluginManager::reenable_plugin`](crate::PluginManager::reenable_plugin).
+    Degraded,
+    /// The plugin failed and is waiting to be restarted.
+    Faulted,
+    /// The plugin failed and is not restarted again until it is re-enabled.
+    Disabled,
+}
+
+/// Record of a plugin loaded in process, kept after it failed so it can be
+/// restarted.
+pub(crate) struct Supervision {
+    pub(crate) id: PluginId,
+    pub(crate) name: String,
+    /// names the settings file of the instance
+    pub(crate) instance: String,
+    /// settings last passed to the plugin
+    pub(crate) settings: Option<StoredSettings>,
+    pub(crate) lib_path: PathBuf,
+    pub(crate) manifest: Option<PluginManifest>,
+    pub(crate) failures: u32,
+    pub(crate) health: PluginHealth,
+    /// set while the plugin is disabled by the host
+    pub(crate) suspended: bool,
+    pub(crate) policy: RestartPolicy,
+}